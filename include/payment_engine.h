@@ -0,0 +1,45 @@
+/* C API of the payment engine, matching `src/ffi.rs`. Link against the `cdylib` built by
+ * `cargo build --release` (e.g. `target/release/libpayment_engine.so`). */
+
+#ifndef PAYMENT_ENGINE_H
+#define PAYMENT_ENGINE_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define PE_OK 0
+#define PE_REJECTED 1
+/* pe_apply_transaction: the transaction it refers to was evicted from the history. */
+#define PE_EVICTED 2
+#define PE_INVALID_ARGUMENT -1
+
+typedef struct PaymentEngine PaymentEngine;
+
+typedef struct PeAccount {
+    uint32_t client;
+    float available;
+    float held;
+    float total;
+    bool locked;
+} PeAccount;
+
+PaymentEngine *pe_engine_new(void);
+
+void pe_engine_free(PaymentEngine *engine);
+
+int pe_apply_transaction(PaymentEngine *engine, const char *type, uint32_t client, uint64_t tx,
+                         float amount);
+
+int pe_get_account(const PaymentEngine *engine, uint32_t client, PeAccount *account);
+
+int pe_export_accounts(const PaymentEngine *engine, const char *path);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* PAYMENT_ENGINE_H */
@@ -1,20 +1,97 @@
+use crate::handler::TransactionHandler;
 use crate::payment_engine::{Transaction, TransactionType};
 use anyhow::{anyhow, Result};
-use serde::{Deserialize, Serialize, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+// balances closer than this are considered equal, matching the four digit precision of the exports.
+pub(crate) const BALANCE_TOLERANCE: f32 = 0.0001;
+
+// The kind of account, setting the rules its transactions follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountClass {
+    // may withdraw held funds, up to the total.
+    #[default]
+    Checking,
+    // may only withdraw available funds, never overdraws.
+    Savings,
+    // may withdraw past its balance during settlement, leaving available negative.
+    Merchant,
+}
+
+// Which transactions leave the history first once an account holds more than the history cap.
+// The oldest is the one with the lowest ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EvictionPolicy {
+    #[default]
+    Oldest,
+    // the oldest transaction that is neither disputed, charged back nor an open authorization; the
+    // history grows past the cap if all of them are.
+    KeepDisputed,
+}
+
+// The error of a dispute, resolve or charge back referring to a transaction evicted from the
+// history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvictedTransaction {
+    pub operation: &'static str,
+    pub tx: u64,
+}
+
+impl fmt::Display for EvictedTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Can't {}; the original transaction {} was evicted from the history.",
+            self.operation, self.tx
+        )
+    }
+}
+
+impl std::error::Error for EvictedTransaction {}
+
+// An account whose balances or locked flag are inconsistent, reported by the invariant checking
+// mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvariantViolation(pub String);
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invariant violation: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvariantViolation {}
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Account {
-    pub(crate) client: u16,
+    pub client: u32,
     #[serde(serialize_with = "float_four_digit_serialize")]
-    pub(crate) available: f32,
+    pub available: f32,
     #[serde(serialize_with = "float_four_digit_serialize")]
-    pub(crate) held: f32,
+    pub held: f32,
     #[serde(serialize_with = "float_four_digit_serialize")]
-    pub(crate) total: f32,
-    pub(crate) locked: bool,
+    pub total: f32,
+    pub locked: bool,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub transactions: BTreeMap<u64, Transaction>, // using BtreeMap to keep the keys sorted
+    #[serde(skip_serializing, skip_deserializing)]
+    pub class: AccountClass,
+    // highest transaction ID evicted from `transactions`, if any.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub evicted_up_to: Option<u64>,
+    // locked by a rule rather than, or as well as, by a charge back; a representment doesn't
+    // unlock it.
     #[serde(skip_serializing, skip_deserializing)]
-    pub(crate) transactions: BTreeMap<u32, Transaction>, // using BtreeMap to keep the keys sorted
+    pub locked_by_rule: bool,
+    // transactions currently charged back, counting those the history no longer holds: evicted,
+    // or kept in the compact index.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub chargebacks: u64,
 }
 
 impl Account {
@@ -24,8 +101,13 @@ impl Account {
     }
 
     pub fn withdraw(&mut self, amount: f32) -> Result<()> {
-        // Perform withdrawal if there is enough money; otherwise ignore.
-        if amount <= self.total {
+        // Perform withdrawal if the account class allows it; otherwise ignore.
+        let allowed = match self.class {
+            AccountClass::Checking => amount <= self.total,
+            AccountClass::Savings => amount <= self.available,
+            AccountClass::Merchant => true,
+        };
+        if allowed {
             self.available -= amount;
             self.total -= amount;
             Ok(())
@@ -33,20 +115,66 @@ impl Account {
             Err(anyhow!("Can't withdraw; insufficient funds."))
         }
     }
-    pub fn dispute(&mut self, tx_id: u32) -> Result<()> {
+    // reserve `amount` of the available funds into held, until it is captured.
+    pub fn authorize(&mut self, amount: f32) -> Result<()> {
+        if amount <= 0.0 {
+            return Err(anyhow!("Can't authorize; amount must be above zero."));
+        }
+        let allowed = match self.class {
+            AccountClass::Merchant => true,
+            AccountClass::Checking | AccountClass::Savings => amount <= self.available,
+        };
+        if !allowed {
+            return Err(anyhow!("Can't authorize; insufficient available funds."));
+        }
+        self.available -= amount;
+        self.held += amount;
+        Ok(())
+    }
+    // withdraw `amount` of the hold of the authorization `tx_id` and release the rest back to
+    // available; an amount of zero releases all of it.
+    pub fn capture(&mut self, tx_id: u64, amount: f32) -> Result<()> {
+        match self.transactions.get_mut(&tx_id) {
+            Some(original_tx) if original_tx.r#type == TransactionType::Authorize => {
+                if original_tx.captured {
+                    return Err(anyhow!(
+                        "Can't capture; authorization was already captured or released."
+                    ));
+                }
+                if amount < 0.0 || amount > original_tx.amount {
+                    return Err(anyhow!(
+                        "Can't capture; amount is not between zero and the authorized {}.",
+                        original_tx.amount
+                    ));
+                }
+                self.held -= original_tx.amount;
+                self.available += original_tx.amount - amount;
+                self.total -= amount;
+                original_tx.captured = true;
+                original_tx.captured_amount = amount;
+                Ok(())
+            }
+            Some(_) => Err(anyhow!(
+                "Can't capture; transaction is not an authorization."
+            )),
+            None => Err(self.missing_transaction("capture", tx_id)),
+        }
+    }
+    pub fn dispute(&mut self, tx_id: u64) -> Result<()> {
         // Perform dispute if the original transactions exists; otherwise ignore.
         if let Some(original_tx) = self.transactions.get_mut(&tx_id) {
+            if original_tx.r#type == TransactionType::Authorize {
+                return Err(anyhow!("Can't dispute; transaction is an authorization."));
+            }
             self.available -= original_tx.amount;
             self.held += original_tx.amount;
             original_tx.disputed = true;
             Ok(())
         } else {
-            Err(anyhow!(
-                "Can't dispute; unable to find the original transaction."
-            ))
+            Err(self.missing_transaction("dispute", tx_id))
         }
     }
-    pub fn resolve(&mut self, tx_id: u32) -> Result<()> {
+    pub fn resolve(&mut self, tx_id: u64) -> Result<()> {
         // Perform resolve if the original transactions exists; otherwise ignore.
         if let Some(original_tx) = self.transactions.get_mut(&tx_id) {
             if original_tx.disputed {
@@ -59,29 +187,112 @@ impl Account {
                 "Can't resolve; transaction is not originally disputed."
             ));
         }
-        Err(anyhow!(
-            "Can't resolve; unable to find the original transaction."
-        ))
+        Err(self.missing_transaction("resolve", tx_id))
     }
-    pub fn charge_back(&mut self, tx_id: u32) -> Result<()> {
+    pub fn charge_back(&mut self, tx_id: u64) -> Result<()> {
         // Perform charge_back if the original transactions exists; otherwise ignore.
         if let Some(original_tx) = self.transactions.get_mut(&tx_id) {
             if original_tx.disputed {
-                self.total += original_tx.amount;
+                self.total -= original_tx.amount;
                 self.held -= original_tx.amount;
                 self.locked = true;
                 original_tx.disputed = false;
+                original_tx.charged_back = true;
+                self.chargebacks += 1;
                 return Ok(());
             }
             return Err(anyhow!(
                 "Can't charge back; transaction is not originally disputed."
             ));
         }
-        Err(anyhow!(
-            "Can't charge back; unable to find the original transaction."
-        ))
+        Err(self.missing_transaction("charge back", tx_id))
+    }
+    // restore the funds of the charged back transaction `tx_id`, unlocking the account if no other
+    // charge back still stands, wherever its transaction is kept, and no rule locked it.
+    pub fn represent(&mut self, tx_id: u64) -> Result<()> {
+        if let Some(original_tx) = self.transactions.get_mut(&tx_id) {
+            if original_tx.charged_back {
+                self.total += original_tx.amount;
+                self.available += original_tx.amount;
+                original_tx.charged_back = false;
+                self.chargebacks = self.chargebacks.saturating_sub(1);
+                if self.chargebacks == 0 && !self.locked_by_rule {
+                    self.locked = false;
+                }
+                return Ok(());
+            }
+            return Err(anyhow!("Can't represent; transaction is not charged back."));
+        }
+        Err(self.missing_transaction("represent", tx_id))
+    }
+    fn missing_transaction(&self, operation: &'static str, tx_id: u64) -> anyhow::Error {
+        match self.evicted_up_to {
+            Some(evicted_up_to) if tx_id <= evicted_up_to => EvictedTransaction {
+                operation,
+                tx: tx_id,
+            }
+            .into(),
+            _ => anyhow!(
+                "Can't {}; unable to find the original transaction.",
+                operation
+            ),
+        }
+    }
+    // drop transactions from the history, as chosen by `policy`, until it holds at most `cap`.
+    pub fn evict(&mut self, cap: usize, policy: EvictionPolicy) {
+        while self.transactions.len() > cap {
+            let evicted = match policy {
+                EvictionPolicy::Oldest => self.transactions.keys().next(),
+                EvictionPolicy::KeepDisputed => self
+                    .transactions
+                    .iter()
+                    .find(|(_, tx)| !tx.disputed && !tx.charged_back && !tx.holds_funds())
+                    .map(|(tx_id, _)| tx_id),
+            };
+            let tx_id = match evicted {
+                Some(tx_id) => *tx_id,
+                None => break,
+            };
+            self.transactions.remove(&tx_id);
+            self.evicted_up_to = self.evicted_up_to.max(Some(tx_id));
+        }
+    }
+    // Verify that the balances are consistent; used by the invariant checking mode after every
+    // transaction.
+    pub fn check_invariants(&self) -> Result<()> {
+        self.check_balances()?;
+        let charged_back = self.transactions.values().any(|tx| tx.charged_back);
+        // the charge back that locked the account may have been evicted, or a rule locked it.
+        if charged_back && !self.locked
+            || self.locked && !charged_back && self.evicted_up_to.is_none() && !self.locked_by_rule
+        {
+            return Err(InvariantViolation(format!(
+                "account locked is {} but charged back is {}.",
+                self.locked, charged_back
+            ))
+            .into());
+        }
+        Ok(())
+    }
+    // the invariants that only depend on the balances.
+    pub fn check_balances(&self) -> Result<()> {
+        if (self.total - (self.available + self.held)).abs() >= BALANCE_TOLERANCE {
+            return Err(InvariantViolation(format!(
+                "total {} does not equal available {} plus held {}.",
+                self.total, self.available, self.held
+            ))
+            .into());
+        }
+        if self.held <= -BALANCE_TOLERANCE {
+            return Err(InvariantViolation(format!("held {} is negative.", self.held)).into());
+        }
+        Ok(())
     }
     pub fn process_transaction(&mut self, transaction: &Transaction) -> Result<()> {
+        // the charge back that locked the account may be reversed.
+        if transaction.r#type == TransactionType::Representment {
+            return self.represent(transaction.tx);
+        }
         if self.locked {
             return Err(anyhow!("Can not process transaction; account is locked.",));
         }
@@ -100,15 +311,32 @@ impl Account {
             TransactionType::Dispute => self.dispute(transaction.tx)?,
             TransactionType::Resolve => self.resolve(transaction.tx)?,
             TransactionType::ChargeBack => self.charge_back(transaction.tx)?,
+            TransactionType::Authorize => {
+                self.authorize(transaction.amount)?;
+                self.transactions
+                    .insert(transaction.tx, transaction.clone());
+            }
+            TransactionType::Capture => self.capture(transaction.tx, transaction.amount)?,
+            TransactionType::Representment => self.represent(transaction.tx)?,
             TransactionType::Unknown(tx) => {
                 return Err(anyhow!("Can't process transaction {}", tx));
             }
         }
         Ok(())
     }
+    pub fn process_custom_transaction(
+        &mut self,
+        handler: &dyn TransactionHandler,
+        transaction: &Transaction,
+    ) -> Result<()> {
+        if self.locked {
+            return Err(anyhow!("Can not process transaction; account is locked.",));
+        }
+        handler.handle(self, transaction)
+    }
 }
 
-fn float_four_digit_serialize<S>(x: &f32, s: S) -> Result<S::Ok, S::Error>
+pub(crate) fn float_four_digit_serialize<S>(x: &f32, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
@@ -120,3 +348,80 @@ where
         }
     }
 }
+
+impl FromStr for EvictionPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "oldest" => Ok(EvictionPolicy::Oldest),
+            "keep-disputed" => Ok(EvictionPolicy::KeepDisputed),
+            _ => Err(anyhow!("unknown eviction policy {}", s)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for AccountClass {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?.to_lowercase();
+        match s.as_str() {
+            "" | "checking" => Ok(AccountClass::Checking),
+            "savings" => Ok(AccountClass::Savings),
+            "merchant" => Ok(AccountClass::Merchant),
+            _ => Err(serde::de::Error::custom(format!(
+                "unknown account class {}",
+                s
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hold::{DisputeDeadline, HoldExpiry, StaleDisputeAction};
+    use crate::payment_engine::PaymentEngine;
+
+    #[test]
+    fn representment_keeps_the_other_charge_back_locked() {
+        for compact in [false, true] {
+            let mut engine = PaymentEngine {
+                compact,
+                dispute_deadline: Some(DisputeDeadline {
+                    expiry: HoldExpiry {
+                        transactions: Some(2),
+                        days: None,
+                    },
+                    action: StaleDisputeAction::ChargeBack,
+                }),
+                ..Default::default()
+            };
+            engine.parse_transactions_from_str(
+                "type,client,tx,amount
+deposit,1,1,10.0
+deposit,1,2,5.0
+dispute,1,2
+dispute,1,1
+chargeback,1,1
+representment,1,1
+",
+            );
+            assert!(
+                engine.failed_transactions.is_empty(),
+                "{:?}",
+                engine.failed_transactions
+            );
+            assert_eq!(engine.closed_stale_disputes, 1);
+            let account = &engine.accounts[&1];
+            // tx 2 was charged back past its deadline, its charge back still stands.
+            assert!(account.locked, "compact: {}", compact);
+            assert_eq!(account.chargebacks, 1);
+            assert_eq!(
+                (account.available, account.held, account.total),
+                (10.0, 0.0, 10.0)
+            );
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use crate::account::Account;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::collections::BTreeMap;
+use std::ops::Index;
+
+// clients from this one on are kept in the map even in the flat layout, so that a single large
+// client ID can't grow the positions to gigabytes.
+const FLAT_CLIENTS: u32 = 1 << 26;
+
+// marks a client without an account in the positions.
+const NO_ACCOUNT: u32 = u32::MAX;
+
+// The accounts of every client, in client order. The compact mode keeps them flat: the accounts in
+// one vector, found through a vector of 32-bit positions indexed by client, instead of a node of
+// the map per account.
+#[derive(Debug, Clone, Default)]
+pub struct Accounts {
+    map: BTreeMap<u32, Account>,
+    flat: Option<Flat>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Flat {
+    // position in `accounts` of the account of every client, or `NO_ACCOUNT`.
+    positions: Vec<u32>,
+    accounts: Vec<Account>,
+}
+
+impl Flat {
+    fn position(&self, client: u32) -> Option<usize> {
+        match self.positions.get(client as usize) {
+            Some(&position) if position != NO_ACCOUNT => Some(position as usize),
+            _ => None,
+        }
+    }
+
+    fn insert(&mut self, client: u32, account: Account) -> Option<Account> {
+        if let Some(position) = self.position(client) {
+            return Some(std::mem::replace(&mut self.accounts[position], account));
+        }
+        if self.positions.len() <= client as usize {
+            self.positions.resize(client as usize + 1, NO_ACCOUNT);
+        }
+        self.positions[client as usize] = self.accounts.len() as u32;
+        self.accounts.push(account);
+        None
+    }
+
+    // the accounts in client order.
+    fn iter(&self) -> impl Iterator<Item = &Account> {
+        self.positions
+            .iter()
+            .filter(|&&position| position != NO_ACCOUNT)
+            .map(|&position| &self.accounts[position as usize])
+    }
+}
+
+impl Accounts {
+    // no accounts, kept flat.
+    pub fn flat() -> Self {
+        Self {
+            flat: Some(Flat::default()),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_flat(&self) -> bool {
+        self.flat.is_some()
+    }
+
+    // keep the accounts flat from now on, moving those already there.
+    pub fn make_flat(&mut self) {
+        if self.is_flat() {
+            return;
+        }
+        let accounts = std::mem::take(&mut self.map);
+        self.flat = Some(Flat::default());
+        self.extend(accounts);
+    }
+
+    // the flat layout holding `client`, if it does.
+    fn flat_for(&self, client: u32) -> Option<&Flat> {
+        self.flat.as_ref().filter(|_| client < FLAT_CLIENTS)
+    }
+
+    pub fn get(&self, client: &u32) -> Option<&Account> {
+        match self.flat_for(*client) {
+            Some(flat) => flat
+                .position(*client)
+                .map(|position| &flat.accounts[position]),
+            None => self.map.get(client),
+        }
+    }
+
+    pub fn get_mut(&mut self, client: &u32) -> Option<&mut Account> {
+        match self.flat.as_mut().filter(|_| *client < FLAT_CLIENTS) {
+            Some(flat) => flat
+                .position(*client)
+                .map(move |position| &mut flat.accounts[position]),
+            None => self.map.get_mut(client),
+        }
+    }
+
+    pub fn contains_key(&self, client: &u32) -> bool {
+        self.get(client).is_some()
+    }
+
+    pub fn insert(&mut self, client: u32, account: Account) -> Option<Account> {
+        match self.flat.as_mut().filter(|_| client < FLAT_CLIENTS) {
+            Some(flat) => flat.insert(client, account),
+            None => self.map.insert(client, account),
+        }
+    }
+
+    // the account of `client`, opened with `open` if it has none yet.
+    pub fn get_or_insert_with(
+        &mut self,
+        client: u32,
+        open: impl FnOnce() -> Account,
+    ) -> &mut Account {
+        if !self.contains_key(&client) {
+            self.insert(client, open());
+        }
+        self.get_mut(&client).expect("the account was just opened")
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len() + self.flat.as_ref().map_or(0, |flat| flat.accounts.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // (client, account) in client order; the flat clients all come before those of the map.
+    pub fn iter(&self) -> impl Iterator<Item = (&u32, &Account)> {
+        self.flat
+            .iter()
+            .flat_map(Flat::iter)
+            .map(|account| (&account.client, account))
+            .chain(self.map.iter())
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Account> {
+        self.iter().map(|(_, account)| account)
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&u32, &mut Account) -> bool) {
+        self.map.retain(|client, account| keep(client, account));
+        if let Some(flat) = self.flat.take() {
+            let mut kept = Flat::default();
+            for mut account in flat.accounts {
+                let client = account.client;
+                if keep(&client, &mut account) {
+                    kept.insert(client, account);
+                }
+            }
+            self.flat = Some(kept);
+        }
+    }
+}
+
+impl Extend<(u32, Account)> for Accounts {
+    fn extend<I: IntoIterator<Item = (u32, Account)>>(&mut self, accounts: I) {
+        for (client, account) in accounts {
+            self.insert(client, account);
+        }
+    }
+}
+
+impl IntoIterator for Accounts {
+    type Item = (u32, Account);
+    type IntoIter = std::vec::IntoIter<(u32, Account)>;
+
+    // (client, account) in client order.
+    fn into_iter(self) -> Self::IntoIter {
+        let mut accounts: Vec<_> = self
+            .flat
+            .into_iter()
+            .flat_map(|flat| flat.accounts)
+            .map(|account| (account.client, account))
+            .collect();
+        accounts.sort_by_key(|(client, _)| *client);
+        accounts.extend(self.map);
+        accounts.into_iter()
+    }
+}
+
+impl FromIterator<(u32, Account)> for Accounts {
+    fn from_iter<I: IntoIterator<Item = (u32, Account)>>(accounts: I) -> Self {
+        let mut collected = Self::default();
+        collected.extend(accounts);
+        collected
+    }
+}
+
+impl Index<&u32> for Accounts {
+    type Output = Account;
+
+    fn index(&self, client: &u32) -> &Account {
+        self.get(client).expect("no account for the client")
+    }
+}
+
+impl Serialize for Accounts {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (client, account) in self.iter() {
+            map.serialize_entry(client, account)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(client: u32, total: f32) -> Account {
+        Account {
+            client,
+            total,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn flat_like_the_map() {
+        let clients = [7, 0, FLAT_CLIENTS, 3, u32::MAX];
+        let mut map = Accounts::default();
+        let mut flat = Accounts::flat();
+        for accounts in [&mut map, &mut flat] {
+            for client in clients {
+                assert!(accounts.insert(client, account(client, 1.0)).is_none());
+            }
+            assert!(accounts.insert(3, account(3, 2.0)).is_some());
+            accounts.get_or_insert_with(9, || account(9, 4.0)).total += 1.0;
+            accounts.get_or_insert_with(9, || account(9, 0.0)).total += 1.0;
+            accounts.retain(|client, _| *client != 7);
+        }
+        let listed = |accounts: &Accounts| -> Vec<(u32, f32)> {
+            accounts
+                .iter()
+                .map(|(client, account)| (*client, account.total))
+                .collect()
+        };
+        let expected = [
+            (0, 1.0),
+            (3, 2.0),
+            (9, 6.0),
+            (FLAT_CLIENTS, 1.0),
+            (u32::MAX, 1.0),
+        ];
+        assert_eq!(listed(&map), expected);
+        assert_eq!(listed(&flat), expected);
+        assert_eq!(flat.len(), expected.len());
+        assert!(flat.get(&7).is_none() && !flat.contains_key(&8));
+        assert_eq!(flat[&u32::MAX].total, 1.0);
+        // the clients past `FLAT_CLIENTS` don't grow the positions.
+        assert_eq!(flat.flat.as_ref().unwrap().positions.len(), 10);
+
+        let owned: Vec<_> = flat
+            .into_iter()
+            .map(|(client, account)| (client, account.total))
+            .collect();
+        assert_eq!(owned, expected);
+
+        map.make_flat();
+        assert!(map.is_flat());
+        assert_eq!(listed(&map), expected);
+    }
+}
@@ -0,0 +1,97 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::str::FromStr;
+
+// position of the amount in a transaction record.
+pub(crate) const AMOUNT_FIELD: usize = 3;
+
+const CURRENCY_SYMBOLS: [char; 13] = [
+    '$', '€', '£', '¥', '₹', '₩', '₽', '₺', '₪', '¢', '₫', '₱', '฿',
+];
+
+// How the amount column is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AmountParsing {
+    // anything that parses as a float.
+    #[default]
+    Standard,
+    // only plain decimals with up to four places, e.g. `12` or `12.3456`.
+    Strict,
+    // also accepts a leading `+`, surrounding spaces and currency symbols, which are stripped.
+    Lenient,
+}
+
+impl AmountParsing {
+    // the amount to deserialize instead of `amount`, if it has to be normalized; empty amounts are
+    // left to the transaction types that don't carry one.
+    pub(crate) fn normalize(&self, amount: &str) -> Result<Option<String>> {
+        match self {
+            AmountParsing::Standard => Ok(None),
+            _ if amount.is_empty() => Ok(None),
+            AmountParsing::Strict => {
+                let (units, places) = amount.split_once('.').unwrap_or((amount, "0"));
+                let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+                if digits(units) && digits(places) && places.len() <= 4 {
+                    Ok(None)
+                } else {
+                    Err(anyhow!(
+                        "Amount {} is not a plain decimal with up to 4 places.",
+                        amount
+                    ))
+                }
+            }
+            AmountParsing::Lenient => {
+                // the sign may come before the currency symbol, as in `+$5`.
+                let normalized = amount.trim();
+                let normalized = normalized.strip_prefix('+').unwrap_or(normalized).trim();
+                let normalized = normalized
+                    .trim_matches(|c| CURRENCY_SYMBOLS.contains(&c))
+                    .trim();
+                if normalized == amount {
+                    Ok(None)
+                } else {
+                    Ok(Some(normalized.to_string()))
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for AmountParsing {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "standard" => Ok(AmountParsing::Standard),
+            "strict" => Ok(AmountParsing::Strict),
+            "lenient" => Ok(AmountParsing::Lenient),
+            _ => Err(anyhow!("unknown amount parsing {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lenient() {
+        let cases = [
+            ("5", None),
+            ("+5", Some("5")),
+            (" 5.25 ", Some("5.25")),
+            ("$5", Some("5")),
+            ("+$5", Some("5")),
+            ("+ $ 5", Some("5")),
+            ("$+5", Some("+5")),
+            ("5 €", Some("5")),
+        ];
+        for (amount, normalized) in cases {
+            let result = AmountParsing::Lenient.normalize(amount).unwrap();
+            assert_eq!(result.as_deref(), normalized, "{:?}", amount);
+            let parsed: f32 = result.as_deref().unwrap_or(amount).parse().unwrap();
+            assert!(parsed > 0.0, "{:?}", amount);
+        }
+    }
+}
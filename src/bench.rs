@@ -0,0 +1,74 @@
+use crate::fast_parse;
+use crate::payment_engine::PaymentEngine;
+use anyhow::Result;
+use std::fs;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Default)]
+pub struct BenchReport {
+    pub rows: u64,
+    pub parsing: Duration,
+    pub applying: Duration,
+    // peak resident set size in kB, only known on Linux.
+    pub peak_rss_kb: Option<u64>,
+}
+
+impl BenchReport {
+    pub fn rows_per_sec(&self) -> f64 {
+        let elapsed = (self.parsing + self.applying).as_secs_f64();
+        if elapsed > 0.0 {
+            self.rows as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+}
+
+impl PaymentEngine {
+    // process the transactions file like `parse_transactions`, timing the parsing and applying
+    // phases separately.
+    pub fn bench(&mut self) -> Result<BenchReport> {
+        let mut report = BenchReport::default();
+        let mut csv_reader = self.new_untrimmed_file_reader()?;
+        let mut record = csv::StringRecord::new();
+        loop {
+            let parsing_started = Instant::now();
+            let has_record = match csv_reader.read_record(&mut record) {
+                Ok(has_record) => has_record,
+                Err(e) => {
+                    eprintln!("Could not read line: {}", e);
+                    report.parsing += parsing_started.elapsed();
+                    continue;
+                }
+            };
+            if !has_record {
+                report.parsing += parsing_started.elapsed();
+                break;
+            }
+            fast_parse::trim_record(&mut record);
+            let deserialized_record = self.deserialize_record(&record);
+            report.parsing += parsing_started.elapsed();
+            report.rows += 1;
+
+            let applying_started = Instant::now();
+            match deserialized_record {
+                Ok(transaction) => self.apply_transaction(&record, &transaction),
+                Err(e) => self.record_failure(&record, e),
+            }
+            report.applying += applying_started.elapsed();
+        }
+        report.peak_rss_kb = peak_rss_kb();
+        Ok(report)
+    }
+}
+
+fn peak_rss_kb() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line.trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()
+}
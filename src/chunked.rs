@@ -0,0 +1,211 @@
+use crate::amount::{AmountParsing, AMOUNT_FIELD};
+use crate::fast_parse;
+use crate::payment_engine::{deserialize_fields, PaymentEngine, Transaction};
+use anyhow::{anyhow, Result};
+use csv::{Position, StringRecord};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// bytes per chunk before aligning on a line end; large enough to keep the per-chunk overhead low.
+const CHUNK_SIZE: u64 = 4 << 20;
+// parsed chunks a parser thread may be ahead of the apply loop by.
+const CHUNKS_AHEAD: usize = 2;
+
+// a transaction deserialized from a record, with its amount if it was normalized.
+type ParsedTransaction = Result<(Transaction, Option<String>)>;
+
+// A record read by a parser thread, with the transaction deserialized from it.
+struct ParsedRecord {
+    record: csv::Result<(StringRecord, ParsedTransaction)>,
+    // the position after the record, within its chunk.
+    end: Position,
+}
+
+struct ParsedChunk {
+    records: Vec<ParsedRecord>,
+    // line terminators in the chunk.
+    lines: u64,
+}
+
+impl PaymentEngine {
+    // same as `parse_transactions` but splits the file into line-aligned chunks parsed by
+    // `threads` threads, while this thread applies the parsed transactions in input order. The
+    // results are the ones of `parse_transactions`, as long as no field holds a quoted line break.
+    pub fn parse_transactions_chunked(&mut self, threads: usize) -> Result<()> {
+        if self.string_clients {
+            return Err(anyhow!(
+                "string clients are numbered in input order and can't be parsed in chunks"
+            ));
+        }
+        if threads == 0 {
+            return Err(anyhow!("at least one parser thread is needed"));
+        }
+        let mut csv_reader = self.new_untrimmed_file_reader()?;
+        self.check_headers(&mut csv_reader);
+        let start = csv_reader.position().clone();
+        let path = self.input_file_path().to_string();
+        let ranges = chunk_ranges(&path, start.byte())?;
+        let amount_parsing = self.amount_parsing;
+        thread::scope(|scope| {
+            let mut receivers = Vec::new();
+            for parser in 0..threads {
+                let (sender, receiver) = mpsc::sync_channel(CHUNKS_AHEAD);
+                receivers.push(receiver);
+                let ranges = ranges.iter().skip(parser).step_by(threads);
+                let path = &path;
+                scope.spawn(move || {
+                    for range in ranges {
+                        let chunk = parse_chunk(path, *range, amount_parsing);
+                        let failed = chunk.is_err();
+                        // the apply loop hung up, e.g. on an abort.
+                        if sender.send(chunk).is_err() || failed {
+                            break;
+                        }
+                    }
+                });
+            }
+            let result = self.apply_chunks(&ranges, &receivers, &start);
+            // let the parsers still running stop.
+            drop(receivers);
+            result
+        })
+    }
+
+    // apply the chunks in order, chunk `i` being parsed by the thread of receiver `i % threads`.
+    fn apply_chunks(
+        &mut self,
+        ranges: &[(u64, u64)],
+        receivers: &[Receiver<Result<ParsedChunk>>],
+        start: &Position,
+    ) -> Result<()> {
+        let failed_before = self.failure_count();
+        let mut read = 0;
+        let mut line = start.line();
+        let mut record_index = start.record();
+        for (index, (chunk_start, _)) in ranges.iter().enumerate() {
+            let chunk = receivers[index % receivers.len()]
+                .recv()
+                .map_err(|_| anyhow!("a parser thread stopped"))??;
+            let chunk_records = chunk.records.len() as u64;
+            let absolute = |position: &Position| {
+                let mut absolute = Position::new();
+                absolute
+                    .set_byte(chunk_start + position.byte())
+                    .set_line(line + position.line() - 1)
+                    .set_record(record_index + position.record());
+                absolute
+            };
+            for parsed in chunk.records {
+                match parsed.record {
+                    Ok((ref record, _)) if self.skips_client(record) => continue,
+                    Ok((mut record, transaction)) => {
+                        record.set_position(record.position().map(absolute));
+                        // the errors quote the position of the record, parse it again to
+                        // get the one in the file.
+                        let transaction = match transaction {
+                            Err(_) => parse_record(&record, self.amount_parsing),
+                            transaction => transaction,
+                        };
+                        match transaction {
+                            Ok((transaction, amount)) => {
+                                if let Some(amount) = &amount {
+                                    self.warn_normalized_amount(&record, amount);
+                                }
+                                self.apply_transaction(&record, &transaction)
+                            }
+                            Err(e) => self.record_failure(&record, e),
+                        }
+                    }
+                    Err(e) => eprintln!("Could not read line: {}", e),
+                }
+                read += 1;
+                let failed = self.failure_count() - failed_before;
+                if let Some(reason) = self.failure_limit_reached(read, failed) {
+                    let end = absolute(&parsed.end);
+                    self.aborted = Some(reason);
+                    self.end_offset = Some(end.byte());
+                    self.end_line = Some(end.line());
+                    return Ok(());
+                }
+            }
+            line += chunk.lines;
+            record_index += chunk_records;
+        }
+        let end_offset = ranges.last().map_or(start.byte(), |(_, end)| *end);
+        self.end_offset = Some(end_offset);
+        self.end_line = Some(line);
+        Ok(())
+    }
+}
+
+// the byte ranges of the chunks the file is split into from `start` on, each ending after a line
+// terminator or at the end of the file.
+fn chunk_ranges(path: &str, start: u64) -> Result<Vec<(u64, u64)>> {
+    let len = fs::metadata(path)?.len();
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut ranges = Vec::new();
+    let mut chunk_start = start;
+    while chunk_start < len {
+        let mut chunk_end = (chunk_start + CHUNK_SIZE).min(len);
+        if chunk_end < len {
+            reader.seek(SeekFrom::Start(chunk_end - 1))?;
+            let mut rest = Vec::new();
+            // with the byte before the terminator, to tell a CRLF.
+            while rest.len() < 2 && reader.read_until(b'\n', &mut rest)? > 0 {}
+            chunk_end += rest.len() as u64 - 1;
+            // the reader positions the record after a CRLF on its LF, so does the next chunk.
+            if rest.ends_with(b"\r\n") {
+                chunk_end -= 1;
+            }
+        }
+        ranges.push((chunk_start, chunk_end));
+        chunk_start = chunk_end;
+    }
+    Ok(ranges)
+}
+
+fn parse_chunk(
+    path: &str,
+    (start, end): (u64, u64),
+    amount_parsing: AmountParsing,
+) -> Result<ParsedChunk> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut builder = PaymentEngine::csv_reader_builder();
+    let mut csv_reader = builder
+        .has_headers(false)
+        .trim(csv::Trim::None)
+        .from_reader(BufReader::new(file.take(end - start)));
+    let mut records = Vec::new();
+    loop {
+        let mut record = StringRecord::new();
+        let read = csv_reader.read_record(&mut record);
+        let parsed = match read {
+            Ok(false) => break,
+            Ok(true) => {
+                fast_parse::trim_record(&mut record);
+                let transaction = parse_record(&record, amount_parsing);
+                Ok((record, transaction))
+            }
+            Err(e) => Err(e),
+        };
+        records.push(ParsedRecord {
+            record: parsed,
+            end: csv_reader.position().clone(),
+        });
+    }
+    Ok(ParsedChunk {
+        records,
+        lines: csv_reader.position().line() - 1,
+    })
+}
+
+// what `deserialize_record` does for numeric clients, without the engine.
+fn parse_record(record: &StringRecord, amount_parsing: AmountParsing) -> ParsedTransaction {
+    let raw_amount = record.get(AMOUNT_FIELD).unwrap_or_default();
+    let amount = amount_parsing.normalize(raw_amount)?;
+    let transaction = deserialize_fields(record, None, amount.as_deref())?;
+    Ok((transaction, amount))
+}
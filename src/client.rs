@@ -0,0 +1,95 @@
+use crate::account::AccountClass;
+use crate::payment_engine::PaymentEngine;
+#[cfg(feature = "fs")]
+use anyhow::Result;
+use csv::StringRecord;
+use serde::{Deserialize, Serialize};
+
+// Human readable details of a client, loaded from an optional `clients.csv`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClientMetadata {
+    pub client: u32,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub tier: String,
+    #[serde(default)]
+    pub country: String,
+    #[serde(default)]
+    pub class: AccountClass,
+}
+
+impl PaymentEngine {
+    // load the `client,name,tier,country,class` metadata file; the metadata is joined into the
+    // exports and reports and the class sets the rules of the client's account.
+    #[cfg(feature = "fs")]
+    pub fn load_client_metadata(&mut self, clients_file_path: String) -> Result<()> {
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_path(clients_file_path)?;
+        for metadata in csv_reader.deserialize::<ClientMetadata>() {
+            let metadata = metadata?;
+            if let Some(account) = self.accounts.get_mut(&metadata.client) {
+                account.class = metadata.class;
+            }
+            self.clients.insert(metadata.client, metadata);
+        }
+        Ok(())
+    }
+
+    // process only the transactions of these clients, as written in the input, skipping the
+    // others.
+    pub fn select_clients<'a>(&mut self, clients: impl IntoIterator<Item = &'a str>) {
+        let selected = clients
+            .into_iter()
+            .map(|client| self.normalize_client(client.trim()))
+            .collect();
+        self.selected_clients = Some(selected);
+    }
+
+    // load the clients to select from a file of one client per line, with an optional `client`
+    // header.
+    #[cfg(feature = "fs")]
+    pub fn select_clients_from_file(&mut self, clients_file_path: String) -> Result<()> {
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_path(clients_file_path)?;
+        let mut clients = Vec::new();
+        for record in csv_reader.records() {
+            let record = record?;
+            match record.get(0) {
+                Some("client") | Some("") | None => {}
+                Some(client) => clients.push(client.to_string()),
+            }
+        }
+        self.select_clients(clients.iter().map(String::as_str));
+        Ok(())
+    }
+
+    // whether the record is skipped for not being of a selected client, counting it if it is.
+    pub(crate) fn skips_client(&mut self, record: &StringRecord) -> bool {
+        let skipped = match &self.selected_clients {
+            Some(selected) => {
+                let client = self.normalize_client(record.get(1).unwrap_or_default());
+                !selected.contains(&client)
+            }
+            None => false,
+        };
+        if skipped {
+            self.skipped_clients += 1;
+        }
+        skipped
+    }
+
+    // numeric clients are compared as numbers, so `042` selects client 42.
+    fn normalize_client(&self, client: &str) -> String {
+        match client.parse::<u32>() {
+            Ok(client) if !self.string_clients => client.to_string(),
+            _ => client.to_string(),
+        }
+    }
+}
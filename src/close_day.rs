@@ -0,0 +1,116 @@
+use crate::hold::{DisputeDeadline, HoldExpiry};
+use crate::journal::Journal;
+use crate::payment_engine::PaymentEngine;
+use crate::settlement;
+use crate::timestamp;
+use anyhow::Result;
+use std::fs;
+use std::sync::Arc;
+
+// names of the files `close_day` writes in the directory of the day.
+pub const JOURNAL_FILE: &str = "journal.csv";
+pub const SNAPSHOT_FILE: &str = "snapshot.bin";
+pub const SETTLEMENT_FILE: &str = "settlement.csv";
+
+// Close the day `date` (`YYYY-MM-DD`): apply the transactions of `input_file_path` timestamped that
+// day, over the state of the previous day's snapshot if given, charging the fees of the fee
+// schedule and expiring the holds and disputes by the policies if given, and write the journal
+// segment, the snapshot to start the next day from and the settlement of the day to `output_dir`,
+// which is created. The engine is returned for the exports; its failure, warning and skip
+// counters only cover the day.
+pub fn close_day(
+    input_file_path: String,
+    date: &str,
+    output_dir: &str,
+    previous_snapshot: Option<String>,
+    fee_schedule_file_path: Option<String>,
+    hold_expiry: Option<HoldExpiry>,
+    dispute_deadline: Option<DisputeDeadline>,
+) -> Result<PaymentEngine> {
+    let mut engine = match previous_snapshot {
+        Some(previous_snapshot) => PaymentEngine::read_snapshot(previous_snapshot)?,
+        None => PaymentEngine::default(),
+    };
+    engine.input_file_path = input_file_path;
+    engine.hold_expiry = hold_expiry;
+    engine.dispute_deadline = dispute_deadline;
+    if let Some(fee_schedule_file_path) = fee_schedule_file_path {
+        engine.load_fee_schedule(fee_schedule_file_path)?;
+    }
+    engine.from_timestamp = Some(timestamp::parse_range_start(date)?);
+    engine.to_timestamp = Some(timestamp::parse_range_end(date)?);
+    fs::create_dir_all(output_dir)?;
+    let journal_file_path = format!("{}/{}", output_dir, JOURNAL_FILE);
+    let journal = Arc::new(Journal::create(journal_file_path.clone())?);
+    engine.register_observer(journal.clone());
+    engine.parse_transactions()?;
+    journal.flush()?;
+    engine.write_snapshot(format!("{}/{}", output_dir, SNAPSHOT_FILE))?;
+    settlement::export_settlement(
+        journal_file_path,
+        format!("{}/{}", output_dir, SETTLEMENT_FILE),
+    )?;
+    Ok(engine)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hold::StaleDisputeAction;
+
+    #[test]
+    fn next_day_keeps_the_keys_and_the_holds() {
+        let dir = std::env::temp_dir().join(format!("pe-close-day-{}", std::process::id()));
+        let dir = dir.to_string_lossy().into_owned();
+        fs::create_dir_all(&dir).unwrap();
+        let input_file_path = format!("{}/transactions.csv", dir);
+        // 2024-03-01 and 2024-03-02.
+        fs::write(
+            &input_file_path,
+            "type,client,tx,amount,timestamp,idempotency_key
+deposit,1,1,10.0,1709251200,k1
+authorize,1,2,4.0,1709251201
+deposit,1,3,5.0,1709251202
+dispute,1,3,0,1709251203
+deposit,1,4,10.0,1709337600,k1
+deposit,1,5,1.0,1709337601
+",
+        )
+        .unwrap();
+        let deadline = DisputeDeadline {
+            expiry: HoldExpiry {
+                transactions: Some(0),
+                days: None,
+            },
+            action: StaleDisputeAction::ChargeBack,
+        };
+        let day = |date: &str, previous: Option<&str>| {
+            close_day(
+                input_file_path.clone(),
+                date,
+                &format!("{}/{}", dir, date),
+                previous.map(|previous| format!("{}/{}/{}", dir, previous, SNAPSHOT_FILE)),
+                None,
+                Some(HoldExpiry {
+                    transactions: Some(2),
+                    days: None,
+                }),
+                Some(deadline),
+            )
+            .unwrap()
+        };
+        let first = day("2024-03-01", None);
+        assert_eq!(first.accounts[&1].held, 9.0);
+        let second = day("2024-03-02", Some("2024-03-01"));
+        fs::remove_dir_all(&dir).unwrap();
+        // the key of the first day is still applied, and its holds expire on the second, before
+        // its deposit is refused by the account the charge back locked.
+        assert_eq!(second.skipped_duplicates, 1);
+        assert_eq!(second.released_holds, 1);
+        assert_eq!(second.closed_stale_disputes, 1);
+        let account = &second.accounts[&1];
+        assert_eq!((account.available, account.held), (10.0, 0.0));
+        assert!(account.locked);
+        assert_eq!(second.failed_transactions.len(), 1);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::account::Account;
+#[cfg(feature = "fs")]
+use crate::disk_index::DiskIndex;
+use crate::payment_engine::{Transaction, TransactionType};
+use crate::slab::{Handle, Slab};
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "fs")]
+use std::path::PathBuf;
+#[cfg(feature = "fs")]
+use std::sync::{Arc, Mutex};
+
+// What the compact mode keeps of a deposit or withdrawal: only what a dispute, resolve or charge
+// back needs, in a single index for all the clients instead of a map per account.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CompactTransaction {
+    pub client: u32,
+    pub amount: f32,
+    pub withdrawal: bool,
+    pub disputed: bool,
+    pub charged_back: bool,
+}
+
+// The compact transactions of every client, stored in a slab and found by client and transaction
+// ID through their 32-bit handle, or in a disk index shared by the clones of the engine. Like the
+// histories of the default mode, two clients may use the same transaction ID.
+#[derive(Debug, Clone, Default)]
+pub struct CompactIndex {
+    handles: HashMap<(u32, u64), Handle>,
+    slab: Slab<CompactTransaction>,
+    #[cfg(feature = "fs")]
+    disk: Option<Arc<Mutex<DiskIndex>>>,
+}
+
+impl CompactIndex {
+    // an empty index kept in the file at `path`.
+    #[cfg(feature = "fs")]
+    pub fn on_disk(path: impl Into<PathBuf>) -> Result<Self> {
+        Ok(Self {
+            disk: Some(Arc::new(Mutex::new(DiskIndex::create(path)?))),
+            ..Default::default()
+        })
+    }
+
+    pub fn get(&self, client: u32, tx: u64) -> Result<Option<CompactTransaction>> {
+        #[cfg(feature = "fs")]
+        if let Some(disk) = &self.disk {
+            return disk
+                .lock()
+                .expect("the disk index is poisoned")
+                .get(client, tx);
+        }
+        Ok(self
+            .handles
+            .get(&(client, tx))
+            .and_then(|handle| self.slab.get(*handle))
+            .copied())
+    }
+
+    // replaces the transaction of the same client and ID, if any.
+    pub fn insert(&mut self, tx: u64, transaction: CompactTransaction) -> Result<()> {
+        #[cfg(feature = "fs")]
+        if let Some(disk) = &self.disk {
+            return disk
+                .lock()
+                .expect("the disk index is poisoned")
+                .insert(tx, transaction);
+        }
+        match self
+            .handles
+            .get(&(transaction.client, tx))
+            .and_then(|handle| self.slab.get_mut(*handle))
+        {
+            Some(stored) => *stored = transaction,
+            None => {
+                let handle = self.slab.insert(transaction);
+                self.handles.insert((transaction.client, tx), handle);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&CompactTransaction) -> bool) -> Result<()> {
+        #[cfg(feature = "fs")]
+        if let Some(disk) = &self.disk {
+            return disk
+                .lock()
+                .expect("the disk index is poisoned")
+                .retain(keep);
+        }
+        let slab = &mut self.slab;
+        self.handles.retain(|_, handle| match slab.get(*handle) {
+            Some(transaction) if keep(transaction) => true,
+            _ => {
+                slab.remove(*handle);
+                false
+            }
+        });
+        Ok(())
+    }
+
+    // merge the index of a clone of this engine; a shared disk index already holds its
+    // transactions.
+    pub fn extend(&mut self, other: CompactIndex) -> Result<()> {
+        for ((_, tx), handle) in other.handles {
+            if let Some(transaction) = other.slab.get(handle) {
+                self.insert(tx, *transaction)?;
+            }
+        }
+        Ok(())
+    }
+
+    // (client, number of disputed transactions)
+    pub fn open_disputes(&self) -> Result<BTreeMap<u32, usize>> {
+        let mut open_disputes = BTreeMap::new();
+        let mut count = |transaction: &CompactTransaction| {
+            if transaction.disputed {
+                *open_disputes.entry(transaction.client).or_default() += 1;
+            }
+        };
+        #[cfg(feature = "fs")]
+        if let Some(disk) = &self.disk {
+            disk.lock()
+                .expect("the disk index is poisoned")
+                .scan(|_, transaction| {
+                    count(&transaction);
+                    Ok(())
+                })?;
+            return Ok(open_disputes);
+        }
+        self.handles
+            .values()
+            .filter_map(|handle| self.slab.get(*handle))
+            .for_each(&mut count);
+        Ok(open_disputes)
+    }
+
+    pub fn len(&self) -> usize {
+        #[cfg(feature = "fs")]
+        if let Some(disk) = &self.disk {
+            return disk.lock().expect("the disk index is poisoned").len() as usize;
+        }
+        self.slab.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// put the transaction `tx` of the account back into its history, so it can be disputed, resolved
+// or charged back like in the default mode.
+pub(crate) fn restore(index: &CompactIndex, account: &mut Account, tx: u64) -> Result<()> {
+    if let Some(stored) = index.get(account.client, tx)? {
+        let r#type = if stored.withdrawal {
+            TransactionType::Withdrawal
+        } else {
+            TransactionType::Deposit
+        };
+        account.transactions.insert(
+            tx,
+            Transaction {
+                r#type,
+                client: stored.client,
+                tx,
+                amount: stored.amount,
+                disputed: stored.disputed,
+                charged_back: stored.charged_back,
+                ..Default::default()
+            },
+        );
+    }
+    Ok(())
+}
+
+// move the history of the account into the index, leaving the account without any but its open
+// authorizations; the captured ones are dropped.
+pub(crate) fn compact(index: &mut CompactIndex, account: &mut Account) -> Result<()> {
+    for (tx, transaction) in std::mem::take(&mut account.transactions) {
+        if transaction.r#type == TransactionType::Authorize {
+            if transaction.holds_funds() {
+                account.transactions.insert(tx, transaction);
+            }
+            continue;
+        }
+        index.insert(
+            tx,
+            CompactTransaction {
+                client: transaction.client,
+                amount: transaction.amount,
+                withdrawal: transaction.r#type == TransactionType::Withdrawal,
+                disputed: transaction.disputed,
+                charged_back: transaction.charged_back,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::payment_engine::PaymentEngine;
+
+    // the same transaction ID for two clients.
+    const SHARED_TX: &str = "type,client,tx,amount
+deposit,1,5,10.0
+deposit,2,5,7.0
+dispute,1,5,0
+dispute,2,5,0
+resolve,2,5,0
+chargeback,1,5,0
+";
+
+    fn run(mut engine: PaymentEngine) -> PaymentEngine {
+        engine.parse_transactions_from_str(SHARED_TX);
+        engine
+    }
+
+    #[test]
+    fn shared_transaction_ids() {
+        let default = run(PaymentEngine::default());
+        assert!(default.failed_transactions.is_empty());
+        let compact = run(PaymentEngine {
+            compact: true,
+            ..Default::default()
+        });
+        assert!(compact.accounts.is_flat() && !default.accounts.is_flat());
+        assert!(compact.same_exports(&default).unwrap());
+        let path = std::env::temp_dir().join(format!("pe-compact-{}.idx", std::process::id()));
+        let mut on_disk = PaymentEngine::default();
+        on_disk
+            .use_disk_transaction_index(path.to_string_lossy().into_owned())
+            .unwrap();
+        let on_disk = run(on_disk);
+        std::fs::remove_file(&path).unwrap();
+        assert!(on_disk.same_exports(&default).unwrap());
+    }
+}
@@ -0,0 +1,277 @@
+use crate::compact::CompactTransaction;
+use anyhow::{anyhow, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// slot layout: occupied, withdrawal, disputed, charged back flags, client, tx, amount, padding.
+const SLOT_SIZE: u64 = 24;
+const INITIAL_SLOTS: u64 = 1024;
+
+// A hash index of compact transactions kept in a file, with open addressing and linear probing
+// over fixed-size slots: memory stays flat however many transactions it holds, at the cost of a
+// read per probe. The file is a scratch file, truncated when the index is created.
+#[derive(Debug)]
+pub struct DiskIndex {
+    path: PathBuf,
+    file: File,
+    slots: u64,
+    len: u64,
+}
+
+impl DiskIndex {
+    pub fn create(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = create_file(&path, INITIAL_SLOTS)?;
+        Ok(Self {
+            path,
+            file,
+            slots: INITIAL_SLOTS,
+            len: 0,
+        })
+    }
+
+    // open the index left in the file at `path` by an earlier one, e.g. to look into it after a
+    // run.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        let size = file.metadata()?.len();
+        if size == 0 || size % SLOT_SIZE != 0 {
+            return Err(anyhow!("{} is not a disk index", path.display()));
+        }
+        let mut index = Self {
+            path,
+            file,
+            slots: size / SLOT_SIZE,
+            len: 0,
+        };
+        let mut len = 0;
+        index.scan(|_, _| {
+            len += 1;
+            Ok(())
+        })?;
+        index.len = len;
+        Ok(index)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, client: u32, tx: u64) -> Result<Option<CompactTransaction>> {
+        let mut slot = self.first_slot(client, tx);
+        loop {
+            match self.read_slot(slot)? {
+                None => return Ok(None),
+                Some((stored_tx, transaction))
+                    if stored_tx == tx && transaction.client == client =>
+                {
+                    return Ok(Some(transaction))
+                }
+                Some(_) => slot = (slot + 1) % self.slots,
+            }
+        }
+    }
+
+    // replaces the transaction of the same client and ID, if any.
+    pub fn insert(&mut self, tx: u64, transaction: CompactTransaction) -> Result<()> {
+        let mut slot = self.first_slot(transaction.client, tx);
+        loop {
+            match self.read_slot(slot)? {
+                Some((stored_tx, stored))
+                    if stored_tx == tx && stored.client == transaction.client =>
+                {
+                    return self.write_slot(slot, tx, &transaction)
+                }
+                Some(_) => slot = (slot + 1) % self.slots,
+                None => break,
+            }
+        }
+        self.write_slot(slot, tx, &transaction)?;
+        self.len += 1;
+        // keep the probes short.
+        if self.len * 2 > self.slots {
+            self.rebuild(self.slots * 2, |_| true)?;
+        }
+        Ok(())
+    }
+
+    pub fn retain(&mut self, keep: impl FnMut(&CompactTransaction) -> bool) -> Result<()> {
+        self.rebuild(self.slots, keep)
+    }
+
+    // copy the kept transactions into a new file of `slots` slots, replacing the current one.
+    fn rebuild(
+        &mut self,
+        slots: u64,
+        mut keep: impl FnMut(&CompactTransaction) -> bool,
+    ) -> Result<()> {
+        let mut rebuilt_path = self.path.clone().into_os_string();
+        rebuilt_path.push(".rebuild");
+        let rebuilt_path = PathBuf::from(rebuilt_path);
+        let mut rebuilt = DiskIndex {
+            file: create_file(&rebuilt_path, slots)?,
+            path: rebuilt_path,
+            slots,
+            len: 0,
+        };
+        self.scan(|tx, transaction| {
+            if keep(&transaction) {
+                rebuilt.insert(tx, transaction)?;
+            }
+            Ok(())
+        })?;
+        fs::rename(&rebuilt.path, &self.path)?;
+        self.file = rebuilt.file;
+        self.slots = rebuilt.slots;
+        self.len = rebuilt.len;
+        Ok(())
+    }
+
+    // visit every transaction of the index, in slot order.
+    pub fn scan(&self, mut visit: impl FnMut(u64, CompactTransaction) -> Result<()>) -> Result<()> {
+        let mut reader = BufReader::new(&self.file);
+        reader.seek(SeekFrom::Start(0))?;
+        let mut bytes = [0; SLOT_SIZE as usize];
+        for _ in 0..self.slots {
+            reader.read_exact(&mut bytes)?;
+            if let Some((tx, transaction)) = decode(&bytes) {
+                visit(tx, transaction)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn first_slot(&self, client: u32, tx: u64) -> u64 {
+        (tx ^ (client as u64).rotate_left(32)).wrapping_mul(0x9E37_79B9_7F4A_7C15) % self.slots
+    }
+
+    fn read_slot(&self, slot: u64) -> Result<Option<(u64, CompactTransaction)>> {
+        let mut bytes = [0; SLOT_SIZE as usize];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(slot * SLOT_SIZE))?;
+        file.read_exact(&mut bytes)?;
+        Ok(decode(&bytes))
+    }
+
+    fn write_slot(&self, slot: u64, tx: u64, transaction: &CompactTransaction) -> Result<()> {
+        let mut bytes = [0; SLOT_SIZE as usize];
+        bytes[0] = 1;
+        bytes[1] = transaction.withdrawal as u8;
+        bytes[2] = transaction.disputed as u8;
+        bytes[3] = transaction.charged_back as u8;
+        bytes[4..8].copy_from_slice(&transaction.client.to_le_bytes());
+        bytes[8..16].copy_from_slice(&tx.to_le_bytes());
+        bytes[16..20].copy_from_slice(&transaction.amount.to_le_bytes());
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(slot * SLOT_SIZE))?;
+        file.write_all(&bytes)?;
+        Ok(())
+    }
+}
+
+fn decode(bytes: &[u8; SLOT_SIZE as usize]) -> Option<(u64, CompactTransaction)> {
+    if bytes[0] == 0 {
+        return None;
+    }
+    let mut client = [0; 4];
+    client.copy_from_slice(&bytes[4..8]);
+    let mut tx = [0; 8];
+    tx.copy_from_slice(&bytes[8..16]);
+    let mut amount = [0; 4];
+    amount.copy_from_slice(&bytes[16..20]);
+    Some((
+        u64::from_le_bytes(tx),
+        CompactTransaction {
+            client: u32::from_le_bytes(client),
+            amount: f32::from_le_bytes(amount),
+            withdrawal: bytes[1] != 0,
+            disputed: bytes[2] != 0,
+            charged_back: bytes[3] != 0,
+        },
+    ))
+}
+
+// a file of `slots` empty slots.
+fn create_file(path: &Path, slots: u64) -> Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    file.set_len(slots * SLOT_SIZE)?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(client: u32, amount: f32) -> CompactTransaction {
+        CompactTransaction {
+            client,
+            amount,
+            ..Default::default()
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pe-{}-{}.idx", name, std::process::id()))
+    }
+
+    #[test]
+    fn collisions() {
+        let path = temp_path("collisions");
+        let mut index = DiskIndex::create(&path).unwrap();
+        // the IDs a multiple of the slots apart probe from the same slot.
+        for i in 0..10 {
+            index
+                .insert(i * INITIAL_SLOTS, transaction(1, i as f32))
+                .unwrap();
+        }
+        // and so may the same ID for other clients.
+        index.insert(0, transaction(2, 100.0)).unwrap();
+        index.insert(0, transaction(1, 50.0)).unwrap();
+        assert_eq!(index.len(), 11);
+        assert_eq!(index.get(1, 0).unwrap(), Some(transaction(1, 50.0)));
+        assert_eq!(index.get(2, 0).unwrap(), Some(transaction(2, 100.0)));
+        for i in 1..10 {
+            let tx = i * INITIAL_SLOTS;
+            assert_eq!(index.get(1, tx).unwrap(), Some(transaction(1, i as f32)));
+        }
+        assert_eq!(index.get(3, 0).unwrap(), None);
+        assert_eq!(index.get(1, 11 * INITIAL_SLOTS).unwrap(), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn growth_and_reopen() {
+        let path = temp_path("growth");
+        let mut index = DiskIndex::create(&path).unwrap();
+        let count = INITIAL_SLOTS * 3;
+        for tx in 0..count {
+            index
+                .insert(tx, transaction(tx as u32 % 7, tx as f32))
+                .unwrap();
+        }
+        assert!(index.slots > INITIAL_SLOTS);
+        assert_eq!(index.len(), count);
+        index.retain(|transaction| transaction.client != 0).unwrap();
+        let kept = (0..count).filter(|tx| tx % 7 != 0).count() as u64;
+        assert_eq!(index.len(), kept);
+        drop(index);
+        let index = DiskIndex::open(&path).unwrap();
+        assert_eq!(index.len(), kept);
+        for tx in 0..count {
+            let expected = (tx % 7 != 0).then(|| transaction(tx as u32 % 7, tx as f32));
+            assert_eq!(index.get(tx as u32 % 7, tx).unwrap(), expected);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
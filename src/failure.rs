@@ -0,0 +1,201 @@
+use crate::account::{EvictedTransaction, InvariantViolation};
+use csv::StringRecord;
+use serde::Serialize;
+use std::fmt;
+#[cfg(feature = "fs")]
+use std::fs::File;
+#[cfg(feature = "fs")]
+use std::sync::{Arc, Mutex};
+
+// written in place of a masked field.
+const MASK: &str = "***";
+
+// positions of the masked fields in a transaction record.
+pub(crate) const CLIENT_FIELD: usize = 1;
+const AMOUNT_FIELD: usize = 3;
+
+// A record the engine could not process, kept field by field so it can be masked on export.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FailedTransaction {
+    pub fields: Vec<String>,
+    pub error: String,
+    // line of the record in the input, if it was read from one.
+    #[serde(skip)]
+    pub line: Option<u64>,
+    // byte offset of the record in the input, if it was read from one.
+    #[serde(skip)]
+    pub byte: Option<u64>,
+    // the untrimmed line of the input, when the engine keeps the failure context.
+    #[serde(skip)]
+    pub raw: Option<String>,
+    #[serde(skip)]
+    pub category: FailureCategory,
+}
+
+// Why a record failed, written next to it in the failed transactions export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureCategory {
+    // the engine refused the record.
+    #[default]
+    Rejected,
+    // the record referred to a transaction evicted from the history.
+    Evicted,
+    // the record left its account inconsistent, see the invariant checking mode.
+    InvariantViolation,
+}
+
+// A failed transactions export written as the failures are recorded, so that a file failing
+// record after record can't exhaust the memory.
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone)]
+pub struct FailureStream {
+    pub(crate) writer: Arc<Mutex<csv::Writer<File>>>,
+    // failures still kept in memory, for the summary and the library users.
+    pub sample_size: usize,
+}
+
+// Which fields of the failed records are redacted in the failed transactions export.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct FailureMask {
+    pub amounts: bool,
+    pub clients: bool,
+}
+
+impl FailedTransaction {
+    pub fn new(record: &StringRecord, error: &anyhow::Error) -> Self {
+        Self {
+            fields: record.iter().map(str::to_string).collect(),
+            error: error.to_string(),
+            line: record.position().map(|position| position.line()),
+            byte: record.position().map(|position| position.byte()),
+            raw: None,
+            category: if error.downcast_ref::<EvictedTransaction>().is_some() {
+                FailureCategory::Evicted
+            } else if error.downcast_ref::<InvariantViolation>().is_some() {
+                FailureCategory::InvariantViolation
+            } else {
+                FailureCategory::Rejected
+            },
+        }
+    }
+
+    // the client field as written in the input.
+    pub fn client(&self) -> Option<&str> {
+        self.fields.get(CLIENT_FIELD).map(|client| client.trim())
+    }
+
+    // the record as it is exported, with the fields selected by `mask` redacted.
+    pub fn masked(&self, mask: &FailureMask) -> String {
+        format!(
+            "{},{}",
+            mask_fields(&self.fields, mask).join(","),
+            self.error
+        )
+    }
+
+    // the exported record followed by its category.
+    pub fn with_category(&self, mask: &FailureMask) -> (String, FailureCategory) {
+        (self.masked(mask), self.category)
+    }
+
+    // the exported record followed by its category, line, byte offset and raw line; the raw line
+    // is left out when `mask` redacts anything, it would leak the redacted fields.
+    pub fn with_context(
+        &self,
+        mask: &FailureMask,
+    ) -> (String, FailureCategory, String, String, String) {
+        let position = |position: Option<u64>| position.map(|p| p.to_string()).unwrap_or_default();
+        let raw = if mask.amounts || mask.clients {
+            String::new()
+        } else {
+            self.raw.clone().unwrap_or_default()
+        };
+        (
+            self.masked(mask),
+            self.category,
+            position(self.line),
+            position(self.byte),
+            raw,
+        )
+    }
+}
+
+// the fields of a record, with those selected by `mask` redacted.
+pub(crate) fn mask_fields<'a>(fields: &'a [String], mask: &FailureMask) -> Vec<&'a str> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(position, field)| match position {
+            CLIENT_FIELD if mask.clients => MASK,
+            AMOUNT_FIELD if mask.amounts => MASK,
+            _ => field.as_str(),
+        })
+        .collect()
+}
+
+impl fmt::Display for FailedTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.masked(&FailureMask::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payment_engine::PaymentEngine;
+    use anyhow::anyhow;
+
+    #[test]
+    fn categories() {
+        let record = StringRecord::from(vec!["dispute", "1", "2", ""]);
+        let rejected = FailedTransaction::new(&record, &anyhow!("Can not process transaction."));
+        assert_eq!(rejected.category, FailureCategory::Rejected);
+        let violation = InvariantViolation("held -1 is negative.".to_string()).into();
+        let violated = FailedTransaction::new(&record, &violation);
+        assert_eq!(violated.category, FailureCategory::InvariantViolation);
+        assert_eq!(violated.error, "Invariant violation: held -1 is negative.");
+
+        let mut wtr = csv::Writer::from_writer(Vec::new());
+        wtr.serialize(violated.with_category(&FailureMask::default()))
+            .unwrap();
+        let exported = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            exported,
+            "\"dispute,1,2,,Invariant violation: held -1 is negative.\",invariant-violation\n"
+        );
+    }
+
+    #[test]
+    fn evicted() {
+        let mut engine = PaymentEngine {
+            history_cap: Some(1),
+            ..Default::default()
+        };
+        engine.parse_transactions_from_str(
+            "type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,1.0
+dispute,1,1
+dispute,1,3
+",
+        );
+        let categories: Vec<_> = engine
+            .failed_transactions
+            .iter()
+            .map(|failed_tx| failed_tx.category)
+            .collect();
+        assert_eq!(
+            categories,
+            [FailureCategory::Evicted, FailureCategory::Rejected]
+        );
+        let mut exported = Vec::new();
+        engine.write_failed_txs(&mut exported).unwrap();
+        assert_eq!(
+            String::from_utf8(exported).unwrap(),
+            "\"dispute,1,1,Can't dispute; the original transaction 1 was evicted from the history.\",evicted
+\"dispute,1,3,Can't dispute; unable to find the original transaction.\",rejected
+"
+        );
+    }
+}
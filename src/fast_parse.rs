@@ -0,0 +1,192 @@
+use crate::payment_engine::{Transaction, TransactionType};
+use csv::StringRecord;
+
+// the most digits an amount may have for its mantissa to be exact in a f64.
+const MAX_AMOUNT_DIGITS: usize = 15;
+
+const POWERS_OF_TEN: [f64; MAX_AMOUNT_DIGITS + 1] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15,
+];
+
+// Reads a record of the plain shape `type,client,tx[,amount[,timestamp[,idempotency_key]]]`
+// straight from its bytes, without going through serde. Returns None for anything else, such as a
+// signed number or an exponent, which is left to the serde deserialization: when it returns a
+// transaction, it is the one serde would have read.
+pub(crate) fn parse_transaction(record: &StringRecord) -> Option<Transaction> {
+    let fields = record.as_byte_record();
+    if fields.len() < 3 || fields.len() > 6 {
+        return None;
+    }
+    let amount = match fields.get(3) {
+        Some(amount) => parse_amount(amount)?,
+        None => 0.0,
+    };
+    let timestamp = match fields.get(4) {
+        Some(b"") | None => None,
+        Some(timestamp) => Some(parse_integer(timestamp)?),
+    };
+    let idempotency_key = match record.get(5) {
+        Some("") | None => None,
+        Some(key) => Some(key.to_string()),
+    };
+    Some(Transaction {
+        r#type: parse_type(fields.get(0)?)?,
+        client: u32::try_from(parse_integer(fields.get(1)?)?).ok()?,
+        tx: parse_integer(fields.get(2)?)?,
+        amount,
+        timestamp,
+        idempotency_key,
+        ..Default::default()
+    })
+}
+
+fn parse_type(field: &[u8]) -> Option<TransactionType> {
+    if !field.is_ascii() {
+        return None;
+    }
+    let lowercase = field.to_ascii_lowercase();
+    Some(match lowercase.as_slice() {
+        b"deposit" => TransactionType::Deposit,
+        b"withdrawal" => TransactionType::Withdrawal,
+        b"dispute" => TransactionType::Dispute,
+        b"resolve" => TransactionType::Resolve,
+        b"chargeback" => TransactionType::ChargeBack,
+        b"authorize" => TransactionType::Authorize,
+        b"capture" => TransactionType::Capture,
+        b"representment" => TransactionType::Representment,
+        _ => TransactionType::Unknown(String::from_utf8(lowercase).ok()?),
+    })
+}
+
+// an unsigned integer of digits only.
+fn parse_integer(field: &[u8]) -> Option<u64> {
+    if field.is_empty() {
+        return None;
+    }
+    field.iter().try_fold(0u64, |value, digit| {
+        if !digit.is_ascii_digit() {
+            return None;
+        }
+        value.checked_mul(10)?.checked_add(u64::from(digit - b'0'))
+    })
+}
+
+// a decimal `digits[.digits]` of up to 15 digits, rounded to the nearest f32 like `str::parse`.
+fn parse_amount(field: &[u8]) -> Option<f32> {
+    let (units, places) = match field.iter().position(|b| *b == b'.') {
+        Some(dot) => (&field[..dot], &field[dot + 1..]),
+        None => (field, &b""[..]),
+    };
+    let digits = units.len() + places.len();
+    if units.is_empty() || field.len() > units.len() && places.is_empty() {
+        return None;
+    }
+    if digits > MAX_AMOUNT_DIGITS {
+        return None;
+    }
+    let mantissa = parse_integer(units)? * POWERS_OF_TEN[places.len()] as u64
+        + if places.is_empty() {
+            0
+        } else {
+            parse_integer(places)?
+        };
+    // both are exact, so the quotient is the decimal rounded once to f64.
+    let value = mantissa as f64 / POWERS_OF_TEN[places.len()];
+    // rounding that to f32 rounds the decimal twice, which only differs from rounding it once when
+    // the f64 falls right between two f32; leave those to `str::parse`.
+    let dropped_bits = value.to_bits() & ((1 << 29) - 1);
+    if dropped_bits == 1 << 28 {
+        return None;
+    }
+    Some(value as f32)
+}
+
+// trim the fields of a record like `csv::Trim::All` does, without copying the record when none of
+// them has surrounding whitespace.
+pub(crate) fn trim_record(record: &mut StringRecord) {
+    // non-ASCII bytes may belong to Unicode whitespace, let `trim` tell.
+    let untrimmed = |byte: &u8| byte.is_ascii_whitespace() || *byte == 0x0b || !byte.is_ascii();
+    let needs_trim = record
+        .as_byte_record()
+        .iter()
+        .any(|field| field.first().is_some_and(untrimmed) || field.last().is_some_and(untrimmed));
+    if needs_trim {
+        record.trim();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // what a transaction is read as, the amount by its bits.
+    fn fields(
+        transaction: &Transaction,
+    ) -> (TransactionType, u32, u64, u32, Option<u64>, Option<String>) {
+        (
+            transaction.r#type.clone(),
+            transaction.client,
+            transaction.tx,
+            transaction.amount.to_bits(),
+            transaction.timestamp,
+            transaction.idempotency_key.clone(),
+        )
+    }
+
+    #[test]
+    fn same_as_serde() {
+        // (record, whether the fast path reads it)
+        let table: &[(&[&str], bool)] = &[
+            (&["deposit", "1", "2", "1.5"], true),
+            (&["Withdrawal", "1", "2", "0.0001"], true),
+            (&["deposit", "1", "2", "12"], true),
+            (&["deposit", "1", "2", "0.1"], true),
+            // the decimal is closer to 6.8319726 but its nearest f64 lies right between that and
+            // 6.831973, which rounding the f64 would pick.
+            (&["deposit", "1", "2", "6.83197283744812"], false),
+            (&["deposit", "1", "2", "0.47584368288517"], false),
+            (&["deposit", "1", "2", "999999999999999"], true),
+            (&["deposit", "1", "2", "1234567890.12345"], true),
+            // more than 4 places.
+            (&["deposit", "1", "2", "1.23456"], true),
+            (&["deposit", "1", "2", "0.123456789012345"], false),
+            (&["deposit", "1", "2", "1e2"], false),
+            (&["deposit", "1", "2", "1.5E-3"], false),
+            (&["deposit", "1", "2", "+5"], false),
+            (&["deposit", "1", "2", "-5"], false),
+            (&["deposit", "1", "2", " 5"], false),
+            (&["deposit", "1", "2", "5 "], false),
+            (&["deposit", " 1", "2", "5"], false),
+            (&["deposit", "1", "2", "5."], false),
+            (&["deposit", "1", "2", ".5"], false),
+            (&["deposit", "1", "2", ""], false),
+            (&["deposit", "", "2", "5"], false),
+            (&["deposit", "1", "", "5"], false),
+            (&["deposit", "4294967296", "2", "5"], false),
+            (&["dispute", "1", "2"], true),
+            (&["dispute", "1", "2", "", ""], false),
+            (&["deposit", "1", "2", "5", "1643673600"], true),
+            (&["deposit", "1", "2", "5", ""], true),
+            (&["deposit", "1", "2", "5", "", "key"], true),
+            (&["deposit", "1", "2", "5", "1643673600", ""], true),
+            (&["bonus", "1", "2", "5"], true),
+            (&["dispute", "1"], false),
+        ];
+        for (fields_of_record, fast) in table {
+            let record = StringRecord::from(fields_of_record.to_vec());
+            let parsed = parse_transaction(&record);
+            assert_eq!(parsed.is_some(), *fast, "{:?}", fields_of_record);
+            if let Some(parsed) = parsed {
+                let deserialized: Transaction = record
+                    .deserialize(None)
+                    .unwrap_or_else(|e| panic!("{:?}: {}", fields_of_record, e));
+                assert_eq!(
+                    fields(&parsed),
+                    fields(&deserialized),
+                    "{:?}",
+                    fields_of_record
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::account::{Account, AccountClass};
+use crate::payment_engine::{PaymentEngine, Transaction, TransactionType};
+#[cfg(feature = "fs")]
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+// type of the linked transactions the fees are applied as.
+pub const FEE_TYPE: &str = "fee";
+
+// A line of the fee schedule: the fee of the deposits or withdrawals of at least `min_amount`, of
+// the accounts of `class` or of any class if unset.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeRule {
+    pub r#type: TransactionType,
+    pub class: Option<AccountClass>,
+    #[serde(default)]
+    pub min_amount: f32,
+    // percentage of the amount.
+    #[serde(default)]
+    pub percent: f32,
+    #[serde(default)]
+    pub fixed: f32,
+}
+
+// The fees charged on the deposits and withdrawals, loaded from a `fees.csv`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FeeSchedule {
+    pub rules: Vec<FeeRule>,
+}
+
+impl FeeSchedule {
+    // load the `type,class,min_amount,percent,fixed` fee schedule file.
+    #[cfg(feature = "fs")]
+    pub fn from_file(fee_schedule_file_path: String) -> Result<Self> {
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_path(fee_schedule_file_path)?;
+        let mut rules = Vec::new();
+        for rule in csv_reader.deserialize::<FeeRule>() {
+            let rule = rule?;
+            if rule.r#type != TransactionType::Deposit && rule.r#type != TransactionType::Withdrawal
+            {
+                return Err(anyhow!(
+                    "fees apply to deposits and withdrawals, not {:?}",
+                    rule.r#type
+                ));
+            }
+            rules.push(rule);
+        }
+        Ok(Self { rules })
+    }
+
+    // the fee of a transaction of an account of `class`, by the rule of the highest tier it
+    // reaches; the rules of its class come before those of any class.
+    pub fn fee(&self, transaction: &Transaction, class: AccountClass) -> Option<f32> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.r#type == transaction.r#type)
+            .filter(|rule| rule.class.is_none_or(|rule_class| rule_class == class))
+            .filter(|rule| transaction.amount >= rule.min_amount)
+            .max_by(|a, b| {
+                (a.class.is_some(), a.min_amount)
+                    .partial_cmp(&(b.class.is_some(), b.min_amount))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .map(|rule| transaction.amount * rule.percent / 100.0 + rule.fixed)
+            .filter(|fee| *fee > 0.0)
+    }
+}
+
+// the linked transaction a fee of `transaction` is applied as, referring to it by its ID.
+pub(crate) fn fee_transaction(transaction: &Transaction, fee: f32) -> Transaction {
+    Transaction {
+        r#type: TransactionType::Unknown(FEE_TYPE.to_string()),
+        client: transaction.client,
+        tx: transaction.tx,
+        amount: fee,
+        timestamp: transaction.timestamp,
+        ..Default::default()
+    }
+}
+
+impl Account {
+    // whether the account, once `transaction` is applied, may still be charged `fee`, by the
+    // withdrawal rules of its class.
+    pub fn covers_fee(&self, transaction: &Transaction, fee: f32) -> bool {
+        let change = match transaction.r#type {
+            TransactionType::Deposit => transaction.amount,
+            TransactionType::Withdrawal => -transaction.amount,
+            _ => 0.0,
+        };
+        match self.class {
+            AccountClass::Checking => fee <= self.total + change,
+            AccountClass::Savings => fee <= self.available + change,
+            AccountClass::Merchant => true,
+        }
+    }
+
+    pub fn charge_fee(&mut self, fee: f32) {
+        self.available -= fee;
+        self.total -= fee;
+    }
+}
+
+impl PaymentEngine {
+    // charge the fees of `fee_schedule_file_path` on every deposit and withdrawal applied.
+    #[cfg(feature = "fs")]
+    pub fn load_fee_schedule(&mut self, fee_schedule_file_path: String) -> Result<()> {
+        self.fee_schedule = Some(FeeSchedule::from_file(fee_schedule_file_path)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+//! C API for embedding the engine, declared in `include/payment_engine.h`.
+//!
+//! Every function returns `PE_OK` (0) on success, `PE_REJECTED` (1) when the engine refused the
+//! request, e.g. a transaction failed or a client is unknown, and `PE_INVALID_ARGUMENT` (-1) for
+//! null pointers and strings that are not valid UTF-8. `pe_apply_transaction` returns
+//! `PE_EVICTED` (2) instead of `PE_REJECTED` when the transaction it refers to was evicted from the
+//! history.
+
+use crate::failure::FailureCategory;
+use crate::payment_engine::PaymentEngine;
+use csv::StringRecord;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+
+pub const PE_OK: c_int = 0;
+pub const PE_REJECTED: c_int = 1;
+pub const PE_EVICTED: c_int = 2;
+pub const PE_INVALID_ARGUMENT: c_int = -1;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PeAccount {
+    pub client: u32,
+    pub available: f32,
+    pub held: f32,
+    pub total: f32,
+    pub locked: bool,
+}
+
+/// Create an empty engine, to be released with `pe_engine_free`.
+#[no_mangle]
+pub extern "C" fn pe_engine_new() -> *mut PaymentEngine {
+    Box::into_raw(Box::default())
+}
+
+/// Release an engine created by `pe_engine_new`.
+///
+/// # Safety
+/// `engine` must be null or a pointer returned by `pe_engine_new` that has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn pe_engine_free(engine: *mut PaymentEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// Apply a transaction; `type` is one of the transaction types of the CSV input, e.g.
+/// `"deposit"`, and `amount` is ignored by the types that don't carry one.
+///
+/// # Safety
+/// `engine` must be a live pointer returned by `pe_engine_new` and `type` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn pe_apply_transaction(
+    engine: *mut PaymentEngine,
+    r#type: *const c_char,
+    client: u32,
+    tx: u64,
+    amount: f32,
+) -> c_int {
+    if engine.is_null() || r#type.is_null() {
+        return PE_INVALID_ARGUMENT;
+    }
+    let engine = &mut *engine;
+    let r#type = match CStr::from_ptr(r#type).to_str() {
+        Ok(r#type) => r#type,
+        Err(_) => return PE_INVALID_ARGUMENT,
+    };
+    let record = StringRecord::from(vec![
+        r#type.to_string(),
+        client.to_string(),
+        tx.to_string(),
+        amount.to_string(),
+    ]);
+    let failed_before = engine.failed_transactions.len();
+    match engine.deserialize_record(&record) {
+        Ok(transaction) => engine.apply_transaction(&record, &transaction),
+        Err(e) => engine.record_failure(&record, e),
+    }
+    match engine.failed_transactions.get(failed_before) {
+        None => PE_OK,
+        Some(failed_tx) if failed_tx.category == FailureCategory::Evicted => PE_EVICTED,
+        Some(_) => PE_REJECTED,
+    }
+}
+
+/// Copy the balances of `client` into `account`.
+///
+/// # Safety
+/// `engine` must be a live pointer returned by `pe_engine_new` and `account` must point to a
+/// writable `PeAccount`.
+#[no_mangle]
+pub unsafe extern "C" fn pe_get_account(
+    engine: *const PaymentEngine,
+    client: u32,
+    account: *mut PeAccount,
+) -> c_int {
+    if engine.is_null() || account.is_null() {
+        return PE_INVALID_ARGUMENT;
+    }
+    match (*engine).accounts.get(&client) {
+        Some(found) => {
+            *account = PeAccount {
+                client: found.client,
+                available: found.available,
+                held: found.held,
+                total: found.total,
+                locked: found.locked,
+            };
+            PE_OK
+        }
+        None => PE_REJECTED,
+    }
+}
+
+/// Write the accounts to the CSV file at `path`, in the same format as the binary's export.
+///
+/// # Safety
+/// `engine` must be a live pointer returned by `pe_engine_new` and `path` a NUL-terminated string.
+#[cfg(feature = "fs")]
+#[no_mangle]
+pub unsafe extern "C" fn pe_export_accounts(
+    engine: *const PaymentEngine,
+    path: *const c_char,
+) -> c_int {
+    if engine.is_null() || path.is_null() {
+        return PE_INVALID_ARGUMENT;
+    }
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => path,
+        Err(_) => return PE_INVALID_ARGUMENT,
+    };
+    match (*engine).export_accounts_to_file(path.to_string()) {
+        Ok(_) => PE_OK,
+        Err(_) => PE_REJECTED,
+    }
+}
+
+#[cfg(all(test, unix, feature = "fs"))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+
+    // the program checks the constants and the layout of the header against those of this module,
+    // then drives the engine through the `cdylib` built along with the tests.
+    const PROGRAM: &str = r#"
+#include <stdio.h>
+#include "payment_engine.h"
+
+_Static_assert(sizeof(PeAccount) == EXPECTED_ACCOUNT_SIZE, "PeAccount layout");
+_Static_assert(PE_OK == EXPECTED_OK && PE_REJECTED == EXPECTED_REJECTED, "status codes");
+_Static_assert(PE_EVICTED == EXPECTED_EVICTED, "status codes");
+_Static_assert(PE_INVALID_ARGUMENT == EXPECTED_INVALID_ARGUMENT, "status codes");
+
+int main(void) {
+    PaymentEngine *engine = pe_engine_new();
+    PeAccount account;
+    printf("%d\n", pe_apply_transaction(engine, "deposit", 7, 1, 10.0f));
+    printf("%d\n", pe_apply_transaction(engine, "withdrawal", 7, 2, 30.0f));
+    printf("%d\n", pe_apply_transaction(engine, NULL, 7, 3, 1.0f));
+    printf("%d\n", pe_get_account(engine, 7, &account));
+    printf("%u %.4f %.4f %.4f %d\n", account.client, account.available, account.held,
+           account.total, account.locked);
+    printf("%d\n", pe_get_account(engine, 8, &account));
+    pe_engine_free(engine);
+    return 0;
+}
+"#;
+
+    #[test]
+    fn header_matches_the_cdylib() {
+        // the test binary is in the directory the `cdylib` is built to.
+        let exe = std::env::current_exe().unwrap();
+        let deps = exe.parent().unwrap();
+        let library = format!(
+            "{}payment_engine{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_SUFFIX
+        );
+        assert!(
+            deps.join(&library).exists(),
+            "{} not built, run the whole `cargo test`",
+            library
+        );
+        let dir = std::env::temp_dir().join(format!("pe-ffi-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("program.c");
+        let program = dir.join("program");
+        fs::write(&source, PROGRAM).unwrap();
+        let include = Path::new(env!("CARGO_MANIFEST_DIR")).join("include");
+        let defines = [
+            ("ACCOUNT_SIZE", std::mem::size_of::<PeAccount>() as c_int),
+            ("OK", PE_OK),
+            ("REJECTED", PE_REJECTED),
+            ("EVICTED", PE_EVICTED),
+            ("INVALID_ARGUMENT", PE_INVALID_ARGUMENT),
+        ];
+        let compiled = Command::new("cc")
+            .args(["-std=c11", "-Wall", "-Wextra", "-Werror"])
+            .args(
+                defines
+                    .iter()
+                    .map(|(name, value)| format!("-DEXPECTED_{}={}", name, value)),
+            )
+            .arg("-I")
+            .arg(&include)
+            .arg(&source)
+            .arg("-o")
+            .arg(&program)
+            .arg(format!("-L{}", deps.display()))
+            .arg(format!("-Wl,-rpath,{}", deps.display()))
+            .arg("-lpayment_engine")
+            .output()
+            .expect("no C compiler `cc` to check the header with");
+        assert!(
+            compiled.status.success(),
+            "{}",
+            String::from_utf8_lossy(&compiled.stderr)
+        );
+        let run = Command::new(&program).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(run.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&run.stdout),
+            "0\n1\n-1\n0\n7 10.0000 0.0000 10.0000 0\n1\n"
+        );
+    }
+}
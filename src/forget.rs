@@ -0,0 +1,346 @@
+#[cfg(feature = "fs")]
+use crate::failure::CLIENT_FIELD;
+#[cfg(feature = "fs")]
+use crate::output::CsvWriterOptions;
+use crate::payment_engine::PaymentEngine;
+use anyhow::{anyhow, Result};
+#[cfg(feature = "fs")]
+use csv::StringRecord;
+
+// the metadata columns holding personal data, blanked when a client is forgotten.
+#[cfg(feature = "fs")]
+const PERSONAL_FIELDS: [&str; 2] = ["name", "country"];
+
+impl PaymentEngine {
+    // drop the transaction history, the failed records, warnings and flags, the idempotency keys,
+    // the open holds and the personal metadata of a client. The balances are kept as they are so
+    // the totals of the engine don't change; an open dispute or authorization stays held and can't
+    // be resolved, charged back or captured afterwards.
+    pub fn forget_client(&mut self, client: u32) -> Result<()> {
+        let account = self
+            .accounts
+            .get_mut(&client)
+            .ok_or_else(|| anyhow!("Can't forget client {}; unable to find it.", client))?;
+        account.transactions.clear();
+        self.transaction_index
+            .retain(|transaction| transaction.client != client)?;
+        let label = self.client_label(client);
+        if let Some(metadata) = self.clients.get_mut(&client) {
+            metadata.name.clear();
+            metadata.country.clear();
+        }
+        self.failed_transactions
+            .retain(|failed_tx| failed_tx.client() != Some(label.as_str()));
+        self.warnings
+            .retain(|warning| warning.client != Some(client));
+        self.flags.retain(|flag| flag.client != client);
+        self.flagged.retain(|(flagged, _)| *flagged != client);
+        self.account_stats.remove(&client);
+        self.rounding.remove(&client);
+        self.applied_idempotency_keys
+            .retain(|_, applied| *applied != client);
+        self.open_authorizations.forget_client(client);
+        self.open_disputes.forget_client(client);
+        Ok(())
+    }
+}
+
+// blank the personal columns of the client's rows in a CSV file with headers, such as an accounts
+// export or a clients file; `client` is matched as written in the file. Returns the number of
+// rows that were anonymized.
+#[cfg(feature = "fs")]
+pub fn anonymize_client_in_file(
+    file_path: String,
+    client: &str,
+    options: &CsvWriterOptions,
+) -> Result<usize> {
+    let (headers, records) = read_file(&file_path, options, true)?;
+    let column = |name: &str| headers.iter().position(|header| header.trim() == name);
+    let client_column =
+        column("client").ok_or_else(|| anyhow!("{} has no client column", file_path))?;
+    let personal_columns = PERSONAL_FIELDS
+        .iter()
+        .filter_map(|field| column(field))
+        .collect::<Vec<usize>>();
+
+    let mut anonymized = 0;
+    let records = records
+        .into_iter()
+        .map(|record| {
+            if record.get(client_column).map(str::trim) != Some(client) {
+                return record;
+            }
+            anonymized += 1;
+            record
+                .iter()
+                .enumerate()
+                .map(|(column, field)| {
+                    if personal_columns.contains(&column) {
+                        ""
+                    } else {
+                        field
+                    }
+                })
+                .collect()
+        })
+        .collect::<Vec<StringRecord>>();
+    write_file(&file_path, options, Some(&headers), &records)?;
+    Ok(anonymized)
+}
+
+// remove the client's records from a failed transactions export. Returns the number of records
+// that were removed.
+#[cfg(feature = "fs")]
+pub fn remove_client_from_failed_file(
+    file_path: String,
+    client: &str,
+    options: &CsvWriterOptions,
+) -> Result<usize> {
+    remove_records(&file_path, options, false, |_, record| {
+        exported_record_client(record.get(0).unwrap_or_default()).as_deref() == Some(client)
+    })
+}
+
+// remove the warnings about the client from a warnings export, those of its records and those
+// raised for it without one. Returns the number of warnings that were removed.
+#[cfg(feature = "fs")]
+pub fn remove_client_from_warnings_file(
+    file_path: String,
+    client: &str,
+    options: &CsvWriterOptions,
+) -> Result<usize> {
+    let flagged = format!("Client {} flagged ", client);
+    remove_records(&file_path, options, true, |headers, record| {
+        let field = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header == name)
+                .and_then(|column| record.get(column))
+                .unwrap_or_default()
+        };
+        exported_record_client(field("record")).as_deref() == Some(client)
+            || field("message").starts_with(&flagged)
+    })
+}
+
+// remove the client's rows from an export with a client column, such as the flags. Returns the
+// number of rows that were removed.
+#[cfg(feature = "fs")]
+pub fn remove_client_from_file(
+    file_path: String,
+    client: &str,
+    options: &CsvWriterOptions,
+) -> Result<usize> {
+    remove_records(&file_path, options, true, |headers, record| {
+        headers
+            .iter()
+            .position(|header| header == "client")
+            .and_then(|column| record.get(column))
+            .map(str::trim)
+            == Some(client)
+    })
+}
+
+// the client of a record as the failed transactions and warnings exports write it, its fields
+// joined by commas whatever the delimiter of the export.
+#[cfg(feature = "fs")]
+fn exported_record_client(exported: &str) -> Option<String> {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(exported.as_bytes())
+        .records()
+        .next()?
+        .ok()?
+        .get(CLIENT_FIELD)
+        .map(|client| client.trim().to_string())
+}
+
+// drop the records of a file for which `remove` is true, returning how many were.
+#[cfg(feature = "fs")]
+fn remove_records(
+    file_path: &str,
+    options: &CsvWriterOptions,
+    has_headers: bool,
+    mut remove: impl FnMut(&StringRecord, &StringRecord) -> bool,
+) -> Result<usize> {
+    let (headers, records) = read_file(file_path, options, has_headers)?;
+    let count = records.len();
+    let kept = records
+        .into_iter()
+        .filter(|record| !remove(&headers, record))
+        .collect::<Vec<StringRecord>>();
+    write_file(file_path, options, has_headers.then_some(&headers), &kept)?;
+    Ok(count - kept.len())
+}
+
+#[cfg(feature = "fs")]
+fn read_file(
+    file_path: &str,
+    options: &CsvWriterOptions,
+    has_headers: bool,
+) -> Result<(StringRecord, Vec<StringRecord>)> {
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .has_headers(has_headers)
+        .flexible(true)
+        .delimiter(options.delimiter)
+        .from_path(file_path)?;
+    let headers = if has_headers {
+        csv_reader.headers()?.clone()
+    } else {
+        StringRecord::new()
+    };
+    let records = csv_reader.records().collect::<csv::Result<Vec<_>>>()?;
+    Ok((headers, records))
+}
+
+#[cfg(feature = "fs")]
+fn write_file(
+    file_path: &str,
+    options: &CsvWriterOptions,
+    headers: Option<&StringRecord>,
+    records: &[StringRecord],
+) -> Result<()> {
+    let mut wtr = options
+        .writer_builder()
+        .flexible(true)
+        .from_path(file_path)?;
+    if let Some(headers) = headers {
+        wtr.write_record(headers)?;
+    }
+    for record in records.iter() {
+        wtr.write_record(record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::{ChargebackRule, RuleAction};
+
+    const TRANSACTIONS: &str = "type,client,tx,amount,timestamp,idempotency_key
+deposit,4242,1,10.0,,k1
+deposit,4242,1,10.0,,k1
+withdrawal,4242,2,50.0
+authorize,4242,3,2.0
+deposit,4242,4,5.0
+dispute,4242,4
+dispute,4242,1
+chargeback,4242,1
+deposit,1,10,1.0,,k2
+withdrawal,1,11,5.0
+";
+
+    fn engine() -> PaymentEngine {
+        let mut engine = PaymentEngine {
+            chargeback_rule: Some(ChargebackRule {
+                max_chargebacks: Some(0),
+                max_chargeback_ratio: None,
+                action: RuleAction::Flag,
+            }),
+            ..Default::default()
+        };
+        engine.parse_transactions_from_str(TRANSACTIONS);
+        engine
+    }
+
+    fn exports(engine: &PaymentEngine) -> [String; 4] {
+        let mut exports: [Vec<u8>; 4] = Default::default();
+        let [accounts, failed_txs, warnings, flags] = &mut exports;
+        engine.write_accounts(accounts).unwrap();
+        engine.write_failed_txs(failed_txs).unwrap();
+        engine.write_warnings(warnings).unwrap();
+        engine.write_flags(flags).unwrap();
+        exports.map(|export| String::from_utf8(export).unwrap())
+    }
+
+    #[test]
+    fn forgotten_everywhere() {
+        let mut engine = engine();
+        let [_, failed_txs, warnings, flags] = exports(&engine);
+        assert!(failed_txs.contains("4242") && warnings.contains("4242") && flags.contains("4242"));
+        let total_before = engine.accounts[&4242].total;
+
+        engine.forget_client(4242).unwrap();
+        let [accounts, failed_txs, warnings, flags] = exports(&engine);
+        for export in [&failed_txs, &warnings, &flags] {
+            assert!(!export.contains("4242"), "{}", export);
+        }
+        // only the balances are left, to keep the totals.
+        let rows = accounts.lines().filter(|row| row.contains("4242"));
+        assert_eq!(rows.count(), 1);
+        assert_eq!(engine.accounts[&4242].total, total_before);
+        assert!(failed_txs.contains("withdrawal,1,11"));
+
+        let path = std::env::temp_dir().join(format!("pe-forget-{}.bin", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        engine.write_snapshot(path.clone()).unwrap();
+        let read = PaymentEngine::read_snapshot(path.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for engine in [&engine, &read] {
+            assert!(engine.accounts[&4242].transactions.is_empty());
+            assert!(engine
+                .applied_idempotency_keys
+                .values()
+                .all(|client| *client != 4242));
+            assert!(engine.applied_idempotency_keys.contains_key("k2"));
+            for open_holds in [&engine.open_authorizations, &engine.open_disputes] {
+                assert!(open_holds.holds().all(|(_, hold)| hold.client != 4242));
+            }
+        }
+        assert!(!engine.account_stats.contains_key(&4242));
+    }
+
+    #[test]
+    fn forgotten_in_exports_with_a_delimiter() {
+        let mut engine = engine();
+        engine.csv_writer_options.delimiter = b';';
+        let options = engine.csv_writer_options;
+        let dir = std::env::temp_dir();
+        let path = |name: &str| {
+            dir.join(format!("pe-forget-{}-{}", std::process::id(), name))
+                .to_string_lossy()
+                .into_owned()
+        };
+        let [accounts, failed_txs, warnings, flags] =
+            ["accounts.csv", "failed.csv", "warnings.csv", "flags.csv"].map(path);
+        engine.export_accounts_to_file(accounts.clone()).unwrap();
+        engine
+            .export_failed_txs_to_file(failed_txs.clone())
+            .unwrap();
+        engine.export_warnings_to_file(warnings.clone()).unwrap();
+        engine.export_flags_to_file(flags.clone()).unwrap();
+
+        assert_eq!(
+            anonymize_client_in_file(accounts.clone(), "4242", &options).unwrap(),
+            1
+        );
+        assert_eq!(
+            remove_client_from_failed_file(failed_txs.clone(), "4242", &options).unwrap(),
+            1
+        );
+        assert_eq!(
+            remove_client_from_warnings_file(warnings.clone(), "4242", &options).unwrap(),
+            2
+        );
+        assert_eq!(
+            remove_client_from_file(flags.clone(), "4242", &options).unwrap(),
+            1
+        );
+        for file in [&failed_txs, &warnings, &flags] {
+            let contents = std::fs::read_to_string(file).unwrap();
+            assert!(!contents.contains("4242"), "{}", contents);
+        }
+        assert!(std::fs::read_to_string(&failed_txs)
+            .unwrap()
+            .contains("withdrawal,1,11"));
+        assert!(std::fs::read_to_string(&accounts)
+            .unwrap()
+            .contains("4242;"));
+        for file in [accounts, failed_txs, warnings, flags] {
+            std::fs::remove_file(file).unwrap();
+        }
+    }
+}
@@ -0,0 +1,12 @@
+use crate::account::Account;
+use crate::payment_engine::Transaction;
+use anyhow::Result;
+use std::fmt::Debug;
+
+// Handles a custom transaction type, i.e. one parsed as `TransactionType::Unknown`, registered
+// with `PaymentEngine::register_handler`. The handler is only called for unlocked accounts and
+// is responsible for the whole effect on the account, including storing the transaction in
+// `account.transactions` if it should be disputable; an error rejects the transaction.
+pub trait TransactionHandler: Debug + Send + Sync {
+    fn handle(&self, account: &mut Account, tx: &Transaction) -> Result<()>;
+}
@@ -0,0 +1,264 @@
+use crate::compact;
+use crate::payment_engine::{PaymentEngine, Transaction, TransactionType};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::str::FromStr;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+// How long a hold may stay open: until this many more transactions have been applied, or this
+// many days after its timestamp by the latest timestamp applied. Holds without a timestamp only
+// expire by `transactions`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct HoldExpiry {
+    pub transactions: Option<u64>,
+    pub days: Option<u64>,
+}
+
+// What is done with a dispute still open past its deadline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StaleDisputeAction {
+    #[default]
+    Resolve,
+    ChargeBack,
+}
+
+impl FromStr for StaleDisputeAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "resolve" => Ok(StaleDisputeAction::Resolve),
+            "chargeback" => Ok(StaleDisputeAction::ChargeBack),
+            _ => Err(anyhow!(
+                "unknown stale dispute action {}, expected resolve or chargeback",
+                s
+            )),
+        }
+    }
+}
+
+// The policy closing the disputes open for longer than `expiry`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DisputeDeadline {
+    pub expiry: HoldExpiry,
+    pub action: StaleDisputeAction,
+}
+
+// a hold, from the time it was placed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Hold {
+    pub(crate) client: u32,
+    pub(crate) tx: u64,
+    pub(crate) timestamp: Option<u64>,
+}
+
+// The holds that may expire, in the order they were placed and, for those with a timestamp, in
+// timestamp order, so a hold that can't expire yet doesn't keep the others from expiring.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpenHolds {
+    // (clock, hold)
+    holds: BTreeMap<u64, Hold>,
+    // (timestamp, clock) of the holds with a timestamp.
+    dated: BTreeSet<(u64, u64)>,
+    // ((client, tx), clock) of the latest hold of each transaction, a dispute may be opened again.
+    placed: HashMap<(u32, u64), u64>,
+    // transactions applied so far.
+    pub(crate) clock: u64,
+    pub(crate) latest_timestamp: Option<u64>,
+}
+
+impl OpenHolds {
+    // (clock, hold) of the open holds, in the order they were placed.
+    #[cfg(feature = "fs")]
+    pub(crate) fn holds(&self) -> impl Iterator<Item = (u64, &Hold)> {
+        self.holds.iter().map(|(clock, hold)| (*clock, hold))
+    }
+
+    // put back a hold placed at `clock`, e.g. read from a snapshot.
+    pub(crate) fn restore(&mut self, clock: u64, hold: Hold) {
+        self.placed.insert((hold.client, hold.tx), clock);
+        if let Some(timestamp) = hold.timestamp {
+            self.dated.insert((timestamp, clock));
+        }
+        self.holds.insert(clock, hold);
+    }
+
+    // drop the holds of `client`, leaving their funds held.
+    pub(crate) fn forget_client(&mut self, client: u32) {
+        let clocks = self
+            .holds
+            .iter()
+            .filter(|(_, hold)| hold.client == client)
+            .map(|(clock, _)| *clock)
+            .collect::<Vec<u64>>();
+        for clock in clocks {
+            self.remove(clock);
+        }
+        self.placed
+            .retain(|(held_client, _), _| *held_client != client);
+    }
+
+    fn tick(&mut self, transaction: &Transaction) {
+        self.clock += 1;
+        self.latest_timestamp = self.latest_timestamp.max(transaction.timestamp);
+    }
+
+    fn place(&mut self, transaction: &Transaction) {
+        let key = (transaction.client, transaction.tx);
+        if let Some(previous) = self.placed.insert(key, self.clock) {
+            self.remove(previous);
+        }
+        self.restore(
+            self.clock,
+            Hold {
+                client: transaction.client,
+                tx: transaction.tx,
+                timestamp: transaction.timestamp,
+            },
+        );
+    }
+
+    fn remove(&mut self, clock: u64) -> Option<Hold> {
+        let hold = self.holds.remove(&clock)?;
+        if let Some(timestamp) = hold.timestamp {
+            self.dated.remove(&(timestamp, clock));
+        }
+        Some(hold)
+    }
+
+    // take the oldest placed hold if it has expired by `transactions`, or else the oldest dated
+    // one if it has expired by `days`.
+    fn pop_expired(&mut self, expiry: &HoldExpiry) -> Option<Hold> {
+        let by_transactions = self.holds.keys().next().copied().filter(|clock| {
+            expiry
+                .transactions
+                .is_some_and(|transactions| self.clock - clock > transactions)
+        });
+        let by_days = || {
+            let &(placed, clock) = self.dated.iter().next()?;
+            let (days, latest) = (expiry.days?, self.latest_timestamp?);
+            (latest.saturating_sub(placed) > days * SECONDS_PER_DAY).then_some(clock)
+        };
+        let clock = by_transactions.or_else(by_days)?;
+        let hold = self.remove(clock)?;
+        self.placed.remove(&(hold.client, hold.tx));
+        Some(hold)
+    }
+}
+
+impl PaymentEngine {
+    // advance the clocks of the holds to the transaction about to be applied, releasing the
+    // authorizations and closing the disputes that expire by then.
+    pub(crate) fn expire_holds(&mut self, transaction: &Transaction) {
+        if let Some(expiry) = self.hold_expiry {
+            self.open_authorizations.tick(transaction);
+            while let Some(hold) = self.open_authorizations.pop_expired(&expiry) {
+                self.close_hold(hold.client, hold.tx, None);
+            }
+        }
+        if let Some(deadline) = self.dispute_deadline {
+            self.open_disputes.tick(transaction);
+            while let Some(hold) = self.open_disputes.pop_expired(&deadline.expiry) {
+                self.close_hold(hold.client, hold.tx, Some(deadline.action));
+            }
+        }
+    }
+
+    // keep track of the hold placed by the transaction just applied, if it may expire.
+    pub(crate) fn place_hold(&mut self, transaction: &Transaction) {
+        match transaction.r#type {
+            TransactionType::Authorize if self.hold_expiry.is_some() => {
+                self.open_authorizations.place(transaction)
+            }
+            TransactionType::Dispute if self.dispute_deadline.is_some() => {
+                self.open_disputes.place(transaction)
+            }
+            _ => {}
+        }
+    }
+
+    // release the authorization `tx` if it is still not captured, or apply `action` to the
+    // transaction `tx` if it is still under dispute.
+    fn close_hold(&mut self, client: u32, tx: u64, action: Option<StaleDisputeAction>) {
+        let account = match self.accounts.get_mut(&client) {
+            Some(account) => account,
+            None => return,
+        };
+        if self.compact && compact::restore(&self.transaction_index, account, tx).is_err() {
+            return;
+        }
+        let was_locked = account.locked;
+        let original_amount = account.transactions.get(&tx).map(|hold| hold.amount);
+        let closed = match (account.transactions.get(&tx), action) {
+            (Some(hold), None) if hold.holds_funds() => account.capture(tx, 0.0).is_ok(),
+            (Some(hold), Some(StaleDisputeAction::Resolve)) if hold.disputed => {
+                account.resolve(tx).is_ok()
+            }
+            (Some(hold), Some(StaleDisputeAction::ChargeBack)) if hold.disputed => {
+                account.charge_back(tx).is_ok()
+            }
+            _ => false,
+        };
+        if closed {
+            if let Some(hold) = account.transactions.get(&tx) {
+                for observer in self.observers.iter() {
+                    match action {
+                        None => observer.on_hold_released(hold, account),
+                        Some(action) => observer.on_stale_dispute_closed(hold, action, account),
+                    }
+                    if account.locked && !was_locked {
+                        observer.on_account_locked(account);
+                    }
+                }
+            }
+            match action {
+                None => self.released_holds += 1,
+                Some(_) => self.closed_stale_disputes += 1,
+            }
+        }
+        if self.compact {
+            if let Err(e) = compact::compact(&mut self.transaction_index, account) {
+                eprintln!("Could not compact the history of client {}: {}", client, e);
+            }
+        }
+        if closed && action == Some(StaleDisputeAction::ChargeBack) {
+            let chargeback = Transaction {
+                r#type: TransactionType::ChargeBack,
+                client,
+                tx,
+                ..Default::default()
+            };
+            self.apply_rules(None, &chargeback, original_amount);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undated_hold_doesnt_block_the_dated_ones() {
+        let mut engine = PaymentEngine {
+            hold_expiry: Some(HoldExpiry {
+                transactions: None,
+                days: Some(5),
+            }),
+            ..Default::default()
+        };
+        engine.parse_transactions_from_str(
+            "type,client,tx,amount,timestamp
+deposit,1,1,100.0,1000000
+authorize,1,2,10.0,
+authorize,1,3,10.0,1000000
+deposit,1,4,1.0,1864000
+",
+        );
+        let account = &engine.accounts[&1];
+        assert_eq!(engine.released_holds, 1);
+        assert_eq!((account.available, account.held), (91.0, 10.0));
+    }
+}
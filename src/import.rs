@@ -0,0 +1,57 @@
+//! Importers turning other file formats into transactions the engine applies directly.
+
+#[cfg(feature = "fix")]
+pub mod fix;
+pub mod iso20022;
+#[cfg(feature = "personal-finance")]
+pub mod ofx;
+#[cfg(feature = "personal-finance")]
+pub mod qif;
+mod xml;
+
+use crate::payment_engine::PaymentEngine;
+use csv::StringRecord;
+
+// An imported movement, before it is given a transaction ID.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportedTransaction {
+    // `deposit` or `withdrawal`.
+    pub r#type: &'static str,
+    // as written in the source, numeric unless the engine reads string clients.
+    pub client: String,
+    pub amount: String,
+    pub timestamp: Option<u64>,
+    // a reference of the source identifying the movement, used as its idempotency key.
+    pub reference: Option<String>,
+}
+
+impl PaymentEngine {
+    // apply imported transactions, numbering them from `first_tx` in order. Returns the next free
+    // transaction ID.
+    pub fn apply_imported_transactions(
+        &mut self,
+        transactions: &[ImportedTransaction],
+        first_tx: u64,
+    ) -> u64 {
+        let mut tx = first_tx;
+        for transaction in transactions.iter() {
+            let record = StringRecord::from(vec![
+                transaction.r#type.to_string(),
+                transaction.client.clone(),
+                tx.to_string(),
+                transaction.amount.clone(),
+                transaction
+                    .timestamp
+                    .map(|timestamp| timestamp.to_string())
+                    .unwrap_or_default(),
+                transaction.reference.clone().unwrap_or_default(),
+            ]);
+            match self.deserialize_record(&record) {
+                Ok(deserialized_record) => self.apply_transaction(&record, &deserialized_record),
+                Err(e) => self.record_failure(&record, e),
+            }
+            tx += 1;
+        }
+        tx
+    }
+}
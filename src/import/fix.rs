@@ -0,0 +1,117 @@
+// FIX drop-copy importer: the fills of the execution reports (`35=8`) of a log become cash
+// movements, the `LastQty` times `LastPx` of a buy being withdrawn and of a sell deposited. Trade
+// cancels reverse the fill they report, other execution types are skipped.
+
+use crate::import::ImportedTransaction;
+use crate::timestamp;
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+
+// parse the execution reports of a drop-copy log, one message per line, the fields separated by
+// SOH or `|`. Every movement belongs to `client` if given, otherwise to the `Account` (tag 1) of
+// its report.
+pub fn parse(log: &str, client: Option<&str>) -> Result<Vec<ImportedTransaction>> {
+    let mut transactions = Vec::new();
+    for (line_number, line) in log.lines().enumerate() {
+        // logs usually prefix the messages with their own timestamp.
+        let message = match line.find("8=FIX") {
+            Some(start) => &line[start..],
+            None => continue,
+        };
+        let fields: BTreeMap<&str, &str> = message
+            .split(['\u{1}', '|'])
+            .filter_map(|field| field.split_once('='))
+            .collect();
+        if fields.get("35") != Some(&"8") {
+            continue;
+        }
+        // `1` and `2` are the partial fill and fill of FIX 4.2 and earlier.
+        let reversal = match fields.get("150") {
+            Some(&"F") | Some(&"1") | Some(&"2") => false,
+            Some(&"H") => true,
+            _ => continue,
+        };
+        let error = |tag: &str| {
+            anyhow!(
+                "execution report on line {} has no tag {}",
+                line_number + 1,
+                tag
+            )
+        };
+        let quantity = fields
+            .get("32")
+            .ok_or_else(|| error("32"))?
+            .parse::<f64>()?;
+        let price = fields
+            .get("31")
+            .ok_or_else(|| error("31"))?
+            .parse::<f64>()?;
+        let buy = match fields.get("54") {
+            Some(&"1") => true,
+            Some(&"2") | Some(&"5") | Some(&"6") => false,
+            _ => return Err(error("54 with a buy or sell side")),
+        };
+        transactions.push(ImportedTransaction {
+            r#type: if buy != reversal {
+                "withdrawal"
+            } else {
+                "deposit"
+            },
+            client: client
+                .or_else(|| fields.get("1").copied())
+                .ok_or_else(|| error("1"))?
+                .to_string(),
+            amount: format!("{:.4}", quantity * price),
+            timestamp: fields
+                .get("60")
+                .map(|time| utc_timestamp(time))
+                .transpose()?,
+            reference: fields.get("17").map(|exec_id| exec_id.to_string()),
+        });
+    }
+    Ok(transactions)
+}
+
+// `YYYYMMDD-HH:MM:SS`, optionally followed by milliseconds, which are dropped.
+fn utc_timestamp(value: &str) -> Result<u64> {
+    let invalid = || anyhow!("{} is not a YYYYMMDD-HH:MM:SS timestamp", value);
+    let (date, time) = value.split_once('-').ok_or_else(invalid)?;
+    let day = timestamp::parse_basic_date(date)?;
+    let mut seconds = 0;
+    for part in time.get(..8).ok_or_else(invalid)?.split(':') {
+        seconds = seconds * 60 + part.parse::<u64>().map_err(|_| invalid())?;
+    }
+    Ok(day + seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(transact_time: &str) -> String {
+        format!(
+            "8=FIX.4.4|35=8|150=F|1=7|17=E1|54=1|32=10|31=2.5|60={}|",
+            transact_time
+        )
+    }
+
+    #[test]
+    fn transact_time() {
+        assert_eq!(
+            parse(&fill("20220201-00:01:02.345"), None).unwrap(),
+            vec![ImportedTransaction {
+                r#type: "withdrawal",
+                client: "7".to_string(),
+                amount: "25.0000".to_string(),
+                timestamp: Some(1643673662),
+                reference: Some("E1".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn non_ascii_transact_time() {
+        assert!(parse(&fill("2022é201-00:01:02"), None).is_err());
+        assert!(parse(&fill("20220201-00:0é:02"), None).is_err());
+    }
+}
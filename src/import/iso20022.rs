@@ -0,0 +1,142 @@
+// ISO 20022 importer: camt.053 bank statements (booked entries become deposits for credits and
+// withdrawals for debits) and pain.001 payment initiations (every credit transfer is a withdrawal
+// from the debtor account).
+
+use crate::import::xml::{self, Element};
+use crate::import::ImportedTransaction;
+use crate::timestamp;
+use anyhow::{anyhow, Result};
+
+// parse a camt.053 or pain.001 message. Every movement belongs to `client` if given, otherwise to
+// the IBAN (or other ID) of its account.
+pub fn parse(document: &str, client: Option<&str>) -> Result<Vec<ImportedTransaction>> {
+    let root = xml::parse(document)?;
+    if let Some(statement) = root.child("BkToCstmrStmt") {
+        Ok(parse_camt053(statement, client))
+    } else if let Some(initiation) = root.child("CstmrCdtTrfInitn") {
+        Ok(parse_pain001(initiation, client))
+    } else {
+        Err(anyhow!(
+            "unsupported ISO 20022 message, expected camt.053 or pain.001"
+        ))
+    }
+}
+
+fn parse_camt053(message: &Element, client: Option<&str>) -> Vec<ImportedTransaction> {
+    let mut transactions = Vec::new();
+    for statement in message.children_named("Stmt") {
+        let account = account_id(statement.child("Acct"));
+        for entry in statement.children_named("Ntry") {
+            // pending entries are not settled yet. The status is the text of `Sts` up to
+            // camt.053.001.07, and nested in `Sts/Cd` from camt.053.001.08 on.
+            if let Some(status) = entry.path_text("Sts/Cd").or_else(|| entry.path_text("Sts")) {
+                if status != "BOOK" {
+                    continue;
+                }
+            }
+            let r#type = match entry.path_text("CdtDbtInd") {
+                Some("CRDT") => "deposit",
+                _ => "withdrawal",
+            };
+            transactions.push(ImportedTransaction {
+                r#type,
+                client: client.unwrap_or(&account).to_string(),
+                amount: entry.path_text("Amt").unwrap_or_default().to_string(),
+                timestamp: date(entry.path("BookgDt")),
+                reference: entry
+                    .path_text("AcctSvcrRef")
+                    .or_else(|| entry.path_text("NtryRef"))
+                    .map(str::to_string),
+            });
+        }
+    }
+    transactions
+}
+
+fn parse_pain001(message: &Element, client: Option<&str>) -> Vec<ImportedTransaction> {
+    let mut transactions = Vec::new();
+    for payment in message.children_named("PmtInf") {
+        let account = account_id(payment.child("DbtrAcct"));
+        let execution_date = date(payment.path("ReqdExctnDt"));
+        for transfer in payment.children_named("CdtTrfTxInf") {
+            transactions.push(ImportedTransaction {
+                r#type: "withdrawal",
+                client: client.unwrap_or(&account).to_string(),
+                amount: transfer
+                    .path_text("Amt/InstdAmt")
+                    .unwrap_or_default()
+                    .to_string(),
+                timestamp: execution_date,
+                reference: transfer
+                    .path_text("PmtId/EndToEndId")
+                    .filter(|reference| *reference != "NOTPROVIDED")
+                    .map(str::to_string),
+            });
+        }
+    }
+    transactions
+}
+
+fn account_id(account: Option<&Element>) -> String {
+    account
+        .and_then(|account| {
+            account
+                .path_text("Id/IBAN")
+                .or_else(|| account.path_text("Id/Othr/Id"))
+        })
+        .unwrap_or_default()
+        .to_string()
+}
+
+// an ISO date, either directly in the element or in its `Dt` or `DtTm` child.
+fn date(element: Option<&Element>) -> Option<u64> {
+    let element = element?;
+    let date = element
+        .path_text("Dt")
+        .or_else(|| element.path_text("DtTm"))
+        .unwrap_or(element.text.trim());
+    timestamp::parse_range_start(date.get(..10)?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statement(booked: &str, pending: &str) -> String {
+        format!(
+            "<Document><BkToCstmrStmt><Stmt><Acct><Id><IBAN>DE02</IBAN></Id></Acct>\
+             <Ntry><NtryRef>1</NtryRef><Amt>10.5</Amt><CdtDbtInd>CRDT</CdtDbtInd>{}\
+             <BookgDt><Dt>2022-02-01</Dt></BookgDt></Ntry>\
+             <Ntry><NtryRef>2</NtryRef><Amt>3</Amt><CdtDbtInd>DBIT</CdtDbtInd>{}</Ntry>\
+             </Stmt></BkToCstmrStmt></Document>",
+            booked, pending
+        )
+    }
+
+    fn assert_booked_only(document: &str) {
+        let transactions = parse(document, None).unwrap();
+        assert_eq!(
+            transactions,
+            vec![ImportedTransaction {
+                r#type: "deposit",
+                client: "DE02".to_string(),
+                amount: "10.5".to_string(),
+                timestamp: Some(1643673600),
+                reference: Some("1".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn camt053_v02_status() {
+        assert_booked_only(&statement("<Sts>BOOK</Sts>", "<Sts>PDNG</Sts>"));
+    }
+
+    #[test]
+    fn camt053_v08_status() {
+        assert_booked_only(&statement(
+            "<Sts><Cd>BOOK</Cd></Sts>",
+            "<Sts><Cd>PDNG</Cd></Sts>",
+        ));
+    }
+}
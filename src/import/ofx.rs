@@ -0,0 +1,86 @@
+// OFX importer, for both the SGML (1.x) and XML (2.x) flavours: every `STMTTRN` is a deposit when
+// its amount is positive and a withdrawal when it is negative.
+
+use crate::import::ImportedTransaction;
+use crate::timestamp;
+use anyhow::{anyhow, Result};
+
+// parse the statement transactions of an OFX export. Every movement belongs to `client` if given,
+// otherwise to the `ACCTID` of its statement.
+pub fn parse(document: &str, client: Option<&str>) -> Result<Vec<ImportedTransaction>> {
+    let mut transactions = Vec::new();
+    let mut account = String::new();
+    let mut current: Option<ImportedTransaction> = None;
+    // SGML leaves the value elements unclosed, so a value runs up to the next tag.
+    for part in document.split('<').skip(1) {
+        let (tag, value) = part
+            .split_once('>')
+            .ok_or_else(|| anyhow!("unterminated tag"))?;
+        let value = value.trim();
+        match (tag.trim(), current.as_mut()) {
+            ("ACCTID", _) => account = value.to_string(),
+            ("STMTTRN", _) => current = Some(ImportedTransaction::default()),
+            ("/STMTTRN", Some(transaction)) => {
+                if transaction.r#type.is_empty() {
+                    return Err(anyhow!("transaction without TRNAMT"));
+                }
+                transaction.client = client.unwrap_or(&account).to_string();
+                transactions.extend(current.take());
+            }
+            ("TRNAMT", Some(transaction)) => {
+                let amount = value.replace(',', "");
+                match amount.strip_prefix('-') {
+                    Some(amount) => {
+                        transaction.r#type = "withdrawal";
+                        transaction.amount = amount.to_string();
+                    }
+                    None => {
+                        transaction.r#type = "deposit";
+                        transaction.amount = amount.trim_start_matches('+').to_string();
+                    }
+                }
+            }
+            ("DTPOSTED", Some(transaction)) => {
+                transaction.timestamp = Some(timestamp::parse_basic_date(value)?)
+            }
+            ("FITID", Some(transaction)) => transaction.reference = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statement(posted: &str) -> String {
+        format!(
+            "<OFX><STMTRS><BANKACCTFROM><ACCTID>42</BANKACCTFROM><BANKTRANLIST>\
+             <STMTTRN><TRNTYPE>DEBIT<DTPOSTED>{}<TRNAMT>-12.50<FITID>A1</STMTTRN>\
+             </BANKTRANLIST></STMTRS></OFX>",
+            posted
+        )
+    }
+
+    #[test]
+    fn posted_date() {
+        let transactions = parse(&statement("20220201120000[-5:EST]"), None).unwrap();
+        assert_eq!(
+            transactions,
+            vec![ImportedTransaction {
+                r#type: "withdrawal",
+                client: "42".to_string(),
+                amount: "12.50".to_string(),
+                timestamp: Some(1643673600),
+                reference: Some("A1".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn non_ascii_posted_date() {
+        assert!(parse(&statement("2022é0201"), None).is_err());
+        assert!(parse(&statement("202€"), None).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+// QIF importer: every record is a deposit when its amount is positive and a withdrawal when it is
+// negative. Investment and memorized records are not supported.
+
+use crate::import::ImportedTransaction;
+use crate::timestamp;
+use anyhow::{anyhow, Result};
+
+// parse the records of a QIF export. Every movement belongs to `client` if given, otherwise to the
+// name of the preceding `!Account` block.
+pub fn parse(document: &str, client: Option<&str>) -> Result<Vec<ImportedTransaction>> {
+    let mut transactions = Vec::new();
+    let mut account = String::new();
+    let mut in_account_block = false;
+    let mut current = ImportedTransaction::default();
+    let document = document.strip_prefix('\u{feff}').unwrap_or(document);
+    for (line_number, line) in document.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('!') {
+            in_account_block = header.eq_ignore_ascii_case("account");
+            continue;
+        }
+        let mut chars = line.chars();
+        let code = match chars.next() {
+            Some(code) => code,
+            None => continue,
+        };
+        let value = chars.as_str().trim();
+        if in_account_block {
+            match code {
+                'N' => account = value.to_string(),
+                '^' => in_account_block = false,
+                _ => {}
+            }
+            continue;
+        }
+        match code {
+            'D' => current.timestamp = date(value),
+            // `U` repeats the amount with more precision in some exports.
+            'T' | 'U' => {
+                let amount = value.replace(',', "");
+                match amount.strip_prefix('-') {
+                    Some(amount) => {
+                        current.r#type = "withdrawal";
+                        current.amount = amount.to_string();
+                    }
+                    None => {
+                        current.r#type = "deposit";
+                        current.amount = amount.trim_start_matches('+').to_string();
+                    }
+                }
+            }
+            'N' if !value.is_empty() => current.reference = Some(value.to_string()),
+            '^' => {
+                if current.r#type.is_empty() {
+                    return Err(anyhow!(
+                        "record ending on line {} has no amount",
+                        line_number + 1
+                    ));
+                }
+                let client = client.unwrap_or(&account);
+                if client.is_empty() {
+                    return Err(anyhow!("the file names no account, pass a client"));
+                }
+                current.client = client.to_string();
+                transactions.push(std::mem::take(&mut current));
+            }
+            _ => {}
+        }
+    }
+    Ok(transactions)
+}
+
+// `M/D/YY`, `M/D'YY` or `M/D/YYYY`; two digit years are 19xx after a `/` and 20xx after a `'`.
+fn date(value: &str) -> Option<u64> {
+    let (month, rest) = value.split_once('/')?;
+    let (day, year, century) = match rest.split_once('\'') {
+        Some((day, year)) => (day, year, 2000),
+        None => {
+            let (day, year) = rest.split_once('/')?;
+            (day, year, 1900)
+        }
+    };
+    let mut year = year.trim().parse::<u32>().ok()?;
+    if year < 100 {
+        year += century;
+    }
+    let month = month.trim().parse::<u32>().ok()?;
+    let day = day.trim().parse::<u32>().ok()?;
+    timestamp::parse_range_start(&format!("{}-{}-{}", year, month, day)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_order_mark() {
+        let document = "\u{feff}!Account\nNChecking\n^\n!Type:Bank\nD2/1'22\nT-1,000.25\nN7\n^\n";
+        assert_eq!(
+            parse(document, None).unwrap(),
+            vec![ImportedTransaction {
+                r#type: "withdrawal",
+                client: "Checking".to_string(),
+                amount: "1000.25".to_string(),
+                timestamp: Some(1643673600),
+                reference: Some("7".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn multi_byte_code() {
+        let document = "!Type:Bank\n€5\nT5\n^\n";
+        assert_eq!(parse(document, Some("1")).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,127 @@
+// A minimal XML reader, enough for the well-formed, data-only messages of the importers: it
+// builds an element tree, drops namespace prefixes, attributes, comments and processing
+// instructions, and decodes the predefined entities.
+
+use anyhow::{anyhow, Result};
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Element {
+    pub(crate) name: String,
+    pub(crate) text: String,
+    pub(crate) children: Vec<Element>,
+}
+
+impl Element {
+    pub(crate) fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    pub(crate) fn children_named<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    // the element at a `/` separated path of child names.
+    pub(crate) fn path(&self, path: &str) -> Option<&Element> {
+        path.split('/')
+            .try_fold(self, |element, name| element.child(name))
+    }
+
+    pub(crate) fn path_text(&self, path: &str) -> Option<&str> {
+        self.path(path).map(|element| element.text.trim())
+    }
+}
+
+pub(crate) fn parse(document: &str) -> Result<Element> {
+    let mut stack = vec![Element::default()];
+    let mut rest = document;
+    while let Some(start) = rest.find('<') {
+        append_text(&mut stack, &rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let end = after
+                .find("]]>")
+                .ok_or_else(|| anyhow!("unterminated CDATA"))?;
+            if let Some(element) = stack.last_mut() {
+                element.text.push_str(&after[..end]);
+            }
+            rest = &after[end + 3..];
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("<!--") {
+            let end = after
+                .find("-->")
+                .ok_or_else(|| anyhow!("unterminated comment"))?;
+            rest = &after[end + 3..];
+            continue;
+        }
+        let end = rest.find('>').ok_or_else(|| anyhow!("unterminated tag"))?;
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+        if let Some(name) = tag.strip_prefix('/') {
+            let element = stack
+                .pop()
+                .filter(|_| !stack.is_empty())
+                .ok_or_else(|| anyhow!("unexpected closing tag {}", name))?;
+            if element.name != local_name(name) {
+                return Err(anyhow!(
+                    "closing tag {} does not match {}",
+                    name,
+                    element.name
+                ));
+            }
+            if let Some(parent) = stack.last_mut() {
+                parent.children.push(element);
+            }
+            continue;
+        }
+        let self_closing = tag.ends_with('/');
+        let name = tag
+            .trim_end_matches('/')
+            .split_whitespace()
+            .next()
+            .ok_or_else(|| anyhow!("empty tag"))?;
+        let element = Element {
+            name: local_name(name).to_string(),
+            ..Default::default()
+        };
+        if self_closing {
+            if let Some(parent) = stack.last_mut() {
+                parent.children.push(element);
+            }
+        } else {
+            stack.push(element);
+        }
+    }
+    append_text(&mut stack, rest);
+    if stack.len() != 1 {
+        return Err(anyhow!("unclosed element {}", stack[stack.len() - 1].name));
+    }
+    stack
+        .pop()
+        .and_then(|document| document.children.into_iter().next())
+        .ok_or_else(|| anyhow!("no root element"))
+}
+
+fn append_text(stack: &mut [Element], text: &str) {
+    if let Some(element) = stack.last_mut() {
+        element.text.push_str(&decode_entities(text));
+    }
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,311 @@
+//! Tamper-evident journal of the engine events.
+//!
+//! Every entry carries the hash of the entry before it and its own hash, a SHA-256 over its
+//! fields and that previous hash, so editing, removing or reordering any entry breaks the chain
+//! from that point on.
+
+use crate::account::Account;
+use crate::fee::FEE_TYPE;
+use crate::hold::StaleDisputeAction;
+use crate::observer::EngineObserver;
+use crate::payment_engine::{Transaction, TransactionType};
+use crate::rules::Flag;
+use crate::signature::{sha256, to_hex};
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::sync::Mutex;
+
+// previous hash of the first entry.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub seq: u64,
+    // applied, fee, failed, released, auto, flagged or locked; auto entries are the resolves and
+    // chargebacks of the stale disputes.
+    pub event: String,
+    pub r#type: String,
+    pub client: String,
+    pub tx: String,
+    pub amount: String,
+    pub detail: String,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl JournalEntry {
+    fn compute_hash(&self) -> String {
+        let chained = [
+            self.seq.to_string().as_str(),
+            &self.event,
+            &self.r#type,
+            &self.client,
+            &self.tx,
+            &self.amount,
+            &self.detail,
+            &self.prev_hash,
+        ]
+        .join("\u{1f}");
+        to_hex(&sha256(chained.as_bytes()))
+    }
+}
+
+#[derive(Debug)]
+struct JournalState {
+    writer: csv::Writer<File>,
+    seq: u64,
+    last_hash: String,
+}
+
+// Writes the engine events to a journal file, registered on the engine as an observer.
+#[derive(Debug)]
+pub struct Journal {
+    state: Mutex<JournalState>,
+    // the clients as written in the input, with string clients.
+    labels: Mutex<HashMap<u32, String>>,
+}
+
+impl Journal {
+    pub fn create(journal_file_path: String) -> Result<Self> {
+        Ok(Self {
+            state: Mutex::new(JournalState {
+                writer: csv::Writer::from_path(journal_file_path)?,
+                seq: 0,
+                last_hash: GENESIS_HASH.to_string(),
+            }),
+            labels: Default::default(),
+        })
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| anyhow!("journal lock poisoned"))?;
+        state.writer.flush()?;
+        Ok(())
+    }
+
+    // the client as written in the input.
+    fn client(&self, client: u32) -> String {
+        self.labels
+            .lock()
+            .ok()
+            .and_then(|labels| labels.get(&client).cloned())
+            .unwrap_or_else(|| client.to_string())
+    }
+
+    fn append(&self, mut entry: JournalEntry) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        state.seq += 1;
+        entry.seq = state.seq;
+        entry.prev_hash = state.last_hash.clone();
+        entry.hash = entry.compute_hash();
+        if let Err(e) = state.writer.serialize(&entry) {
+            eprintln!("Could not write journal entry {}: {}", entry.seq, e);
+            return;
+        }
+        state.last_hash = entry.hash;
+    }
+}
+
+impl EngineObserver for Journal {
+    fn on_transaction_applied(&self, transaction: &Transaction, account: &Account) {
+        // a charge back or representment carries no amount, journal the one it took or gave back.
+        let amount = match (
+            &transaction.r#type,
+            account.transactions.get(&transaction.tx),
+        ) {
+            (TransactionType::ChargeBack | TransactionType::Representment, Some(original)) => {
+                original.amount
+            }
+            _ => transaction.amount,
+        };
+        self.append(JournalEntry {
+            event: "applied".to_string(),
+            r#type: format!("{:?}", transaction.r#type).to_lowercase(),
+            client: self.client(transaction.client),
+            tx: transaction.tx.to_string(),
+            amount: amount.to_string(),
+            ..Default::default()
+        });
+    }
+
+    fn on_transaction_failed(&self, record: &StringRecord, error: &anyhow::Error) {
+        self.append(JournalEntry {
+            event: "failed".to_string(),
+            r#type: record.get(0).unwrap_or_default().to_string(),
+            client: record.get(1).unwrap_or_default().to_string(),
+            tx: record.get(2).unwrap_or_default().to_string(),
+            amount: record.get(3).unwrap_or_default().to_string(),
+            detail: error.to_string(),
+            ..Default::default()
+        });
+    }
+
+    fn on_fee_charged(&self, fee: &Transaction, _account: &Account) {
+        self.append(JournalEntry {
+            event: "fee".to_string(),
+            r#type: FEE_TYPE.to_string(),
+            client: self.client(fee.client),
+            tx: fee.tx.to_string(),
+            amount: fee.amount.to_string(),
+            ..Default::default()
+        });
+    }
+
+    fn on_hold_released(&self, hold: &Transaction, _account: &Account) {
+        self.append(JournalEntry {
+            event: "released".to_string(),
+            r#type: format!("{:?}", hold.r#type).to_lowercase(),
+            client: self.client(hold.client),
+            tx: hold.tx.to_string(),
+            amount: hold.amount.to_string(),
+            detail: "hold expired".to_string(),
+            ..Default::default()
+        });
+    }
+
+    fn on_stale_dispute_closed(
+        &self,
+        transaction: &Transaction,
+        action: StaleDisputeAction,
+        _account: &Account,
+    ) {
+        let r#type = match action {
+            StaleDisputeAction::Resolve => "resolve",
+            StaleDisputeAction::ChargeBack => "chargeback",
+        };
+        self.append(JournalEntry {
+            event: "auto".to_string(),
+            r#type: r#type.to_string(),
+            client: self.client(transaction.client),
+            tx: transaction.tx.to_string(),
+            amount: transaction.amount.to_string(),
+            detail: "dispute past its deadline".to_string(),
+            ..Default::default()
+        });
+    }
+
+    fn on_client_labeled(&self, client: u32, label: &str) {
+        if let Ok(mut labels) = self.labels.lock() {
+            labels.insert(client, label.to_string());
+        }
+    }
+
+    fn on_account_flagged(&self, flag: &Flag) {
+        self.append(JournalEntry {
+            event: "flagged".to_string(),
+            r#type: flag.rule.clone(),
+            client: self.client(flag.client),
+            tx: flag.tx.to_string(),
+            detail: flag.detail.clone(),
+            ..Default::default()
+        });
+    }
+
+    fn on_account_locked(&self, account: &Account) {
+        self.append(JournalEntry {
+            event: "locked".to_string(),
+            client: self.client(account.client),
+            ..Default::default()
+        });
+    }
+}
+
+// walk the hash chain of a journal file. Returns the number of entries, or an error naming the
+// first entry that doesn't match.
+pub fn verify_journal(journal_file_path: String) -> Result<u64> {
+    let mut csv_reader = csv::Reader::from_path(journal_file_path)?;
+    let mut expected_prev_hash = GENESIS_HASH.to_string();
+    let mut expected_seq = 1;
+    for entry in csv_reader.deserialize::<JournalEntry>() {
+        let entry = entry?;
+        if entry.seq != expected_seq {
+            return Err(anyhow!(
+                "entry {} found where entry {} was expected",
+                entry.seq,
+                expected_seq
+            ));
+        }
+        if entry.prev_hash != expected_prev_hash {
+            return Err(anyhow!(
+                "entry {} is not chained to the one before",
+                entry.seq
+            ));
+        }
+        if entry.compute_hash() != entry.hash {
+            return Err(anyhow!("entry {} has been modified", entry.seq));
+        }
+        expected_prev_hash = entry.hash;
+        expected_seq += 1;
+    }
+    Ok(expected_seq - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payment_engine::PaymentEngine;
+    use std::fs;
+    use std::sync::Arc;
+
+    #[test]
+    fn tampered_entry() {
+        let path = std::env::temp_dir().join(format!("pe-journal-{}.csv", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let journal = Arc::new(Journal::create(path.clone()).unwrap());
+        let mut engine = PaymentEngine::default();
+        engine.register_observer(journal.clone());
+        engine.parse_transactions_from_str(
+            "type,client,tx,amount
+deposit,1,1,10.0
+withdrawal,1,2,3.0
+withdrawal,1,3,30.0
+",
+        );
+        journal.flush().unwrap();
+        assert_eq!(verify_journal(path.clone()).unwrap(), 3);
+        let journal = fs::read_to_string(&path).unwrap();
+        fs::write(&path, journal.replacen(",3,", ",4,", 1)).unwrap();
+        let error = verify_journal(path.clone()).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.to_string(), "entry 2 has been modified");
+    }
+
+    #[test]
+    fn string_clients_as_written() {
+        let path =
+            std::env::temp_dir().join(format!("pe-journal-labels-{}.csv", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let mut engine = PaymentEngine {
+            string_clients: true,
+            ..Default::default()
+        };
+        engine.parse_transactions_from_str("type,client,tx,amount\ndeposit,bob,1,1.0\n");
+        // registered after the first label, which it is still given.
+        let journal = Arc::new(Journal::create(path.clone()).unwrap());
+        engine.register_observer(journal.clone());
+        engine.parse_transactions_from_str(
+            "type,client,tx,amount
+deposit,alice,2,10.0
+withdrawal,alice,3,3.0
+deposit,bob,4,2.0
+",
+        );
+        journal.flush().unwrap();
+        let settlements = crate::settlement::settle_journal(path.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+        let settled: Vec<_> = settlements
+            .iter()
+            .map(|settlement| (settlement.client.as_str(), settlement.net))
+            .collect();
+        assert_eq!(settled, [("alice", 7.0), ("bob", 2.0)]);
+    }
+}
@@ -0,0 +1,53 @@
+pub mod account;
+pub mod accounts;
+pub mod amount;
+#[cfg(feature = "fs")]
+pub mod bench;
+#[cfg(feature = "fs")]
+pub mod chunked;
+pub mod client;
+#[cfg(feature = "fs")]
+pub mod close_day;
+pub mod compact;
+#[cfg(feature = "fs")]
+pub mod disk_index;
+pub mod failure;
+pub mod fast_parse;
+pub mod fee;
+pub mod ffi;
+pub mod forget;
+pub mod handler;
+pub mod hold;
+pub mod import;
+#[cfg(feature = "fs")]
+pub mod journal;
+pub mod observer;
+pub mod output;
+pub mod payment_engine;
+pub mod precision;
+#[cfg(feature = "fs")]
+pub mod reconcile;
+pub mod repl;
+#[cfg(feature = "fs")]
+pub mod resume;
+pub mod rules;
+#[cfg(feature = "fs")]
+pub mod settlement;
+#[cfg(feature = "fs")]
+pub mod shard;
+#[cfg(feature = "fs")]
+pub mod signature;
+pub mod simulate;
+pub mod slab;
+#[cfg(feature = "fs")]
+pub mod snapshot;
+#[cfg(feature = "fs")]
+pub mod sort;
+#[cfg(feature = "fs")]
+pub mod statement;
+#[cfg(feature = "fs")]
+pub mod tenant;
+pub mod timestamp;
+pub mod warning;
+#[cfg(feature = "wasm")]
+pub mod wasm;
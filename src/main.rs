@@ -1,29 +1,1117 @@
-mod account;
-mod payment_engine;
+use clap::{AppSettings, ArgEnum, Parser, Subcommand};
+use payment_engine::account::EvictionPolicy;
+use payment_engine::amount::AmountParsing;
+use payment_engine::close_day;
+use payment_engine::failure::FailureMask;
+use payment_engine::forget;
+use payment_engine::hold::{DisputeDeadline, HoldExpiry, StaleDisputeAction};
+use payment_engine::import::{self, ImportedTransaction};
+use payment_engine::journal::{self, Journal};
+use payment_engine::output::{AccountColumn, CsvWriterOptions, LineEnding, QuoteStyle};
+use payment_engine::payment_engine::{PaymentEngine, Transaction};
+use payment_engine::repl;
+use payment_engine::resume::StartPosition;
+use payment_engine::rules::{ChargebackRule, RiskRule, RuleAction};
+use payment_engine::settlement;
+use payment_engine::shard;
+use payment_engine::signature;
+use payment_engine::sort;
+use payment_engine::tenant::{self, TenantEngines};
+use payment_engine::timestamp;
+use std::path::Path;
+use std::sync::Arc;
 
-use crate::payment_engine::PaymentEngine;
-use std::env;
+#[derive(Parser)]
+#[clap(
+    setting(AppSettings::ArgsNegateSubcommands),
+    setting(AppSettings::SubcommandsNegateReqs)
+)]
+struct Cli {
+    /// Name of the transactions file in `./csvFiles`.
+    #[clap(required = true)]
+    transactions: Option<String>,
+    /// Sort the transactions by timestamp before applying them, into `<transactions>.sorted` in
+    /// `./csvFiles`.
+    #[clap(long)]
+    sort_by_timestamp: bool,
+    /// Records held in memory at once by `--sort-by-timestamp`; more are sorted through scratch
+    /// files.
+    #[clap(long, default_value = "1000000")]
+    sort_run_size: usize,
+    /// Verify the account invariants after every transaction.
+    #[clap(long)]
+    check_invariants: bool,
+    /// Name of the client metadata file (`client,name,tier,country,class`) in `./csvFiles`.
+    #[clap(long)]
+    clients: Option<String>,
+    /// Name of the fee schedule file (`type,class,min_amount,percent,fixed`) in `./csvFiles`, whose
+    /// fees are charged on the deposits and withdrawals.
+    #[clap(long)]
+    fees: Option<String>,
+    /// Release the holds of the authorizations not captured after this many more transactions.
+    #[clap(long, conflicts_with = "threads")]
+    hold_expiry_transactions: Option<u64>,
+    /// Release the holds of the authorizations not captured after this many days, by the latest
+    /// timestamp applied.
+    #[clap(long, conflicts_with = "threads")]
+    hold_expiry_days: Option<u64>,
+    /// Lock the accounts with more than this many charge backs over the run.
+    #[clap(long)]
+    max_chargebacks: Option<usize>,
+    /// Lock the accounts whose charge backs are worth more than this fraction of their deposits
+    /// over the run, e.g. `0.05`.
+    #[clap(long)]
+    max_chargeback_ratio: Option<f64>,
+    /// What the charge back limits do: `lock` the account, or only `flag` it for review.
+    #[clap(long, default_value = "lock")]
+    chargeback_action: RuleAction,
+    /// Flag the accounts whose risk score, from 0 to 100, goes over this.
+    #[clap(long)]
+    max_risk_score: Option<f64>,
+    /// Transactions an account must have attempted before its risk score is checked.
+    #[clap(long, default_value = "10")]
+    risk_min_transactions: usize,
+    /// Close the disputes still open after this many more transactions, by `--stale-disputes`.
+    #[clap(long, conflicts_with = "threads")]
+    dispute_deadline_transactions: Option<u64>,
+    /// Close the disputes still open after this many days, by the latest timestamp applied.
+    #[clap(long, conflicts_with = "threads")]
+    dispute_deadline_days: Option<u64>,
+    /// What closes a dispute past its deadline: `resolve` or `chargeback`.
+    #[clap(long, default_value = "resolve")]
+    stale_disputes: StaleDisputeAction,
+    /// Read the client column as an opaque string, e.g. a UUID, instead of a number.
+    #[clap(long)]
+    string_clients: bool,
+    /// Process only the transactions of these clients, skipping the others.
+    #[clap(long, use_delimiter = true, conflicts_with = "only-clients-file")]
+    only_clients: Vec<String>,
+    /// Name of a file in `./csvFiles` listing the clients to process, one per line.
+    #[clap(long)]
+    only_clients_file: Option<String>,
+    /// Apply only the transactions from this time on, as `YYYY-MM-DD` or unix seconds.
+    #[clap(long)]
+    from: Option<String>,
+    /// Apply only the transactions up to this time, inclusive, as `YYYY-MM-DD` or unix seconds.
+    #[clap(long)]
+    to: Option<String>,
+    /// How the amount column is read: `standard`, `strict` (plain decimals with up to 4 places) or
+    /// `lenient` (also strips a leading `+`, spaces and currency symbols).
+    #[clap(long, default_value = "standard")]
+    amount_parsing: AmountParsing,
+    /// Raise a warning for every amount normalized by `--amount-parsing lenient`.
+    #[clap(long)]
+    warn_normalized_amounts: bool,
+    /// Columns of `accounts.csv`, in order, among `client`, `available`, `held`, `total`, `locked`,
+    /// `name`, `tier`, `country`, `class`, `open_disputes` and `risk_score`.
+    #[clap(long, use_delimiter = true)]
+    columns: Vec<AccountColumn>,
+    /// When the fields of the exports are quoted: `always`, `necessary`, `non-numeric` or `never`.
+    #[clap(long, default_value = "necessary")]
+    quote_style: QuoteStyle,
+    /// Line ending of the exports: `lf` or `crlf`.
+    #[clap(long, default_value = "lf")]
+    line_ending: LineEnding,
+    /// Field delimiter of the exports, a single ASCII character.
+    #[clap(long, default_value = ",", validator = ascii_delimiter)]
+    delimiter: char,
+    /// Abort, exiting with status 3, once more than this many transactions have failed.
+    #[clap(long, conflicts_with_all = &["threads", "tenants", "tenant"])]
+    max_failures: Option<usize>,
+    /// Abort, exiting with status 3, once more than this fraction of the records have failed, e.g.
+    /// `0.01`; checked from the 1000th record on.
+    #[clap(long, conflicts_with_all = &["threads", "tenants", "tenant"])]
+    max_failure_rate: Option<f64>,
+    /// Skip the records before this byte offset of the transactions file, the start of a line such
+    /// as the end offset reported by an earlier run.
+    #[clap(long, conflicts_with_all = &["start-line", "threads", "tenants", "tenant"])]
+    start_offset: Option<u64>,
+    /// Skip the records before this line of the transactions file, the header being line 1.
+    #[clap(long, conflicts_with_all = &["threads", "tenants", "tenant"])]
+    start_line: Option<u64>,
+    /// Name of the accounts export in `./csvFiles` holding the balances of the records before
+    /// `--start-offset` or `--start-line`.
+    #[clap(long, conflicts_with_all = &["string-clients", "tenants", "tenant"])]
+    resume_accounts: Option<String>,
+    /// Add the line, byte offset and untrimmed input line of every record to `failed.csv`.
+    #[clap(long)]
+    failure_context: bool,
+    /// Write every failure to `failed.csv` as it occurs, keeping only the first ones in memory.
+    #[clap(long, conflicts_with_all = &["threads", "tenants", "tenant"])]
+    stream_failures: bool,
+    /// Failures kept in memory by `--stream-failures`.
+    #[clap(long, default_value = "1000")]
+    failure_sample: usize,
+    /// Track the rounding of the f32 amounts and balances against the decimal input, writing the
+    /// accounts whose total rounding is larger than this to `precision.csv`.
+    #[clap(long)]
+    precision_audit: Option<f64>,
+    /// Redact the amounts in `failed.csv`.
+    #[clap(long)]
+    mask_amounts: bool,
+    /// Redact the client IDs in `failed.csv`.
+    #[clap(long)]
+    mask_clients: bool,
+    /// Sign `accounts.csv`, `failed.csv` and `warnings.csv` with the HMAC-SHA256 key read from this
+    /// file.
+    #[clap(long)]
+    sign_key_file: Option<String>,
+    /// Name of the binary snapshot of the accounts and their histories to write in `./csvFiles`,
+    /// for `query`.
+    #[clap(long, conflicts_with_all = &["compact", "transaction-index-file", "tenants", "tenant"])]
+    snapshot: Option<String>,
+    /// Name of the hash-chained event journal to write in `./csvFiles`.
+    #[clap(long, conflicts_with_all = &["tenants", "tenant"])]
+    journal: Option<String>,
+    /// Name of the settlement file to write in `./csvFiles` from the journal at the end of the run:
+    /// the net movement of every client.
+    #[clap(long, requires = "journal")]
+    settlement: Option<String>,
+    /// Partition the transactions by their `tenant` column, exporting to `./csvFiles/<tenant>/`.
+    #[clap(long)]
+    tenants: bool,
+    /// Tenant of the transactions without a `tenant` column, exporting to `./csvFiles/<tenant>/`.
+    #[clap(long)]
+    tenant: Option<String>,
+    /// Keep the transaction history in a single compact index, for runs with very many clients.
+    #[clap(long)]
+    compact: bool,
+    /// Keep the history in a hash index in this file of `./csvFiles` instead of memory; implies
+    /// `--compact`.
+    #[clap(long, conflicts_with = "history-cap")]
+    transaction_index_file: Option<String>,
+    /// Keep at most this many transactions in the history of each account.
+    #[clap(long, conflicts_with = "compact")]
+    history_cap: Option<usize>,
+    /// Which transactions leave a full history first: `oldest` or `keep-disputed`.
+    #[clap(long, default_value = "oldest")]
+    eviction: EvictionPolicy,
+    /// Split the clients over this many shards, each applied by its own thread; 0 for one per core.
+    #[clap(long, conflicts_with_all = &["string-clients", "journal", "tenants", "tenant"])]
+    threads: Option<usize>,
+    /// Parse the file in line-aligned chunks on this many threads, applying the transactions in
+    /// input order on the main one; 0 for one per core.
+    #[clap(
+        long,
+        conflicts_with_all = &["threads", "string-clients", "tenants", "tenant", "start-offset", "start-line"]
+    )]
+    parse_threads: Option<usize>,
+    /// Pin every shard thread to a core.
+    #[clap(long, requires = "threads")]
+    pin_threads: bool,
+    /// Also process the transactions sequentially and fail unless the sharded exports are identical.
+    #[clap(long, requires = "threads", conflicts_with = "transaction-index-file")]
+    verify_determinism: bool,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Merge several account exports into a single one.
+    Merge {
+        /// Names of the account exports in `./csvFiles`.
+        #[clap(required = true, min_values = 2)]
+        inputs: Vec<String>,
+        /// Name of the merged export in `./csvFiles`.
+        #[clap(long, default_value = "accounts.csv")]
+        output: String,
+    },
+    /// Remove a client's failed records and personal data from the exports.
+    Forget {
+        /// The client, as written in the exports.
+        client: String,
+        /// Name of the accounts export in `./csvFiles`.
+        #[clap(long, default_value = "accounts.csv")]
+        accounts: String,
+        /// Name of the failed transactions export in `./csvFiles`.
+        #[clap(long, default_value = "failed.csv")]
+        failed: String,
+        /// Name of the client metadata file in `./csvFiles`.
+        #[clap(long)]
+        clients: Option<String>,
+        /// Name of the warnings export in `./csvFiles`, skipped if there is none.
+        #[clap(long, default_value = "warnings.csv")]
+        warnings: String,
+        /// Name of the flags export in `./csvFiles`, skipped if there is none.
+        #[clap(long, default_value = "flags.csv")]
+        flags: String,
+        /// Name of a snapshot in `./csvFiles` to forget the client in too.
+        #[clap(long)]
+        snapshot: Option<String>,
+        /// Name of the journal in `./csvFiles`; a journal can't be rewritten, so forgetting is
+        /// refused while it exists.
+        #[clap(long)]
+        journal: Option<String>,
+        /// Field delimiter of the exports, a single ASCII character.
+        #[clap(long, default_value = ",", validator = ascii_delimiter)]
+        delimiter: char,
+    },
+    /// Process a transactions file without writing outputs and report the throughput.
+    Bench {
+        /// Name of the transactions file in `./csvFiles`.
+        transactions: String,
+    },
+    /// Compare the computed balances against a settlement statement.
+    Reconcile {
+        /// Name of the transactions file in `./csvFiles`.
+        transactions: String,
+        /// Name of the statement file (`client,tx,amount`) in `./csvFiles`.
+        statement: String,
+        /// Name of the break report in `./csvFiles`.
+        #[clap(long, default_value = "breaks.csv")]
+        output: String,
+    },
+    /// Load a transactions file and explore the resulting state interactively.
+    Repl {
+        /// Name of the transactions file in `./csvFiles`.
+        transactions: String,
+        /// Read the client column as an opaque string, e.g. a UUID, instead of a number.
+        #[clap(long)]
+        string_clients: bool,
+    },
+    /// Produce per-client statements for a period.
+    Statement {
+        /// Name of the transactions file in `./csvFiles`, with a `timestamp` column.
+        transactions: String,
+        /// Start of the period, as `YYYY-MM-DD` or unix seconds.
+        #[clap(long)]
+        from: String,
+        /// Inclusive end of the period, as `YYYY-MM-DD` or unix seconds.
+        #[clap(long)]
+        to: String,
+        /// Name of the statements file in `./csvFiles`.
+        #[clap(long, default_value = "statements.csv")]
+        output: String,
+        /// Write the statements as printable text instead of CSV.
+        #[clap(long)]
+        text: bool,
+        /// Name of the client metadata file (`client,name,tier,country,class`) in `./csvFiles`.
+        #[clap(long)]
+        clients: Option<String>,
+    },
+    /// Check the signature footer of a signed export.
+    Verify {
+        /// Name of the signed file in `./csvFiles`.
+        file: String,
+        /// File holding the HMAC-SHA256 key.
+        #[clap(long)]
+        key_file: String,
+    },
+    /// Settle a bank file of another format, writing the usual exports.
+    Import {
+        /// Format of the file.
+        #[clap(arg_enum)]
+        format: ImportFormat,
+        /// Name of the file in `./csvFiles`.
+        file: String,
+        /// Numeric client receiving every movement; by default each account is its own client,
+        /// labelled by its IBAN or other identifier.
+        #[clap(long)]
+        client: Option<u32>,
+        /// Transaction ID of the first movement, the following ones being numbered in order.
+        #[clap(long, default_value = "1")]
+        first_tx: u64,
+    },
+    /// Show a client's balances, open disputes and the transactions with the highest IDs from a
+    /// snapshot.
+    Query {
+        /// Name of the snapshot in `./csvFiles`.
+        #[clap(long)]
+        snapshot: String,
+        /// The client, as written in the input.
+        #[clap(long)]
+        client: String,
+        /// Number of transactions shown, those with the highest IDs.
+        #[clap(long, default_value = "10")]
+        highest: usize,
+    },
+    /// Close a day: apply its transactions over the previous day's snapshot and write the
+    /// journal, snapshot, settlement and exports of the day to `./csvFiles/<date>/`.
+    CloseDay {
+        /// Name of the transactions file in `./csvFiles`; only those of the day are applied.
+        transactions: String,
+        /// The day to close, as `YYYY-MM-DD`.
+        #[clap(long)]
+        date: String,
+        /// Date of the previous day closed, whose snapshot the day starts from.
+        #[clap(long)]
+        previous: Option<String>,
+        /// Name of the fee schedule file in `./csvFiles`.
+        #[clap(long)]
+        fees: Option<String>,
+        /// Release the holds of the authorizations not captured after this many more transactions.
+        #[clap(long)]
+        hold_expiry_transactions: Option<u64>,
+        /// Release the holds of the authorizations not captured after this many days.
+        #[clap(long)]
+        hold_expiry_days: Option<u64>,
+        /// Close the disputes still open after this many more transactions, by `--stale-disputes`.
+        #[clap(long)]
+        dispute_deadline_transactions: Option<u64>,
+        /// Close the disputes still open after this many days.
+        #[clap(long)]
+        dispute_deadline_days: Option<u64>,
+        /// What closes a dispute past its deadline: `resolve` or `chargeback`.
+        #[clap(long, default_value = "resolve")]
+        stale_disputes: StaleDisputeAction,
+    },
+    /// Work with event journals.
+    Journal {
+        #[clap(subcommand)]
+        command: JournalCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum JournalCommand {
+    /// Check that no entry of a journal has been edited, removed or reordered.
+    Verify {
+        /// Name of the journal in `./csvFiles`.
+        file: String,
+    },
+}
+
+#[derive(Clone, Copy, ArgEnum)]
+enum ImportFormat {
+    /// ISO 20022 camt.053 bank statement or pain.001 payment initiation.
+    Iso20022,
+    /// FIX drop-copy log of execution reports.
+    #[cfg(feature = "fix")]
+    Fix,
+    /// OFX bank or credit card statement.
+    #[cfg(feature = "personal-finance")]
+    Ofx,
+    /// QIF bank or cash account.
+    #[cfg(feature = "personal-finance")]
+    Qif,
+}
+
+// exit status of a run stopped by `--max-failures` or `--max-failure-rate`, after writing the
+// partial exports.
+const ABORTED_EXIT_CODE: i32 = 3;
+
+// the delimiter is written as a single byte.
+fn ascii_delimiter(delimiter: &str) -> Result<(), String> {
+    let mut chars = delimiter.chars();
+    match (chars.next(), chars.next()) {
+        (Some(delimiter), None) if delimiter.is_ascii() => Ok(()),
+        _ => Err("must be a single ASCII character".to_string()),
+    }
+}
+
+fn csv_file_path(file_name: &str) -> String {
+    format!("./csvFiles/{}", file_name)
+}
+
+// the key is the file's contents, without a trailing newline.
+fn read_key_file(key_file_path: &str) -> Vec<u8> {
+    let mut key = std::fs::read(key_file_path).expect("reading the key file failed.");
+    while key.ends_with(b"\n") || key.ends_with(b"\r") {
+        key.pop();
+    }
+    key
+}
 
 fn main() {
-    let args: Vec<String> = env::args().skip(1).collect();
+    let mut cli = Cli::parse();
+    match cli.command.take() {
+        Some(Command::Merge { inputs, output }) => merge(inputs, output),
+        Some(Command::Forget {
+            client,
+            accounts,
+            failed,
+            clients,
+            warnings,
+            flags,
+            snapshot,
+            journal,
+            delimiter,
+        }) => forget(
+            client,
+            ForgetFiles {
+                accounts,
+                failed,
+                clients,
+                warnings,
+                flags,
+                snapshot,
+                journal,
+            },
+            delimiter,
+        ),
+        Some(Command::Bench { transactions }) => bench(transactions),
+        Some(Command::Reconcile {
+            transactions,
+            statement,
+            output,
+        }) => reconcile(transactions, statement, output),
+        Some(Command::Repl {
+            transactions,
+            string_clients,
+        }) => repl(transactions, string_clients),
+        Some(Command::Statement {
+            transactions,
+            from,
+            to,
+            output,
+            text,
+            clients,
+        }) => statement(transactions, from, to, output, text, clients),
+        Some(Command::Verify { file, key_file }) => verify(file, key_file),
+        Some(Command::Import {
+            format,
+            file,
+            client,
+            first_tx,
+        }) => import(format, file, client, first_tx),
+        Some(Command::Query {
+            snapshot,
+            client,
+            highest,
+        }) => query(snapshot, client, highest),
+        Some(Command::CloseDay {
+            transactions,
+            date,
+            previous,
+            fees,
+            hold_expiry_transactions,
+            hold_expiry_days,
+            dispute_deadline_transactions,
+            dispute_deadline_days,
+            stale_disputes,
+        }) => close_day(
+            transactions,
+            date,
+            previous,
+            fees,
+            hold_expiry(hold_expiry_transactions, hold_expiry_days),
+            hold_expiry(dispute_deadline_transactions, dispute_deadline_days).map(|expiry| {
+                DisputeDeadline {
+                    expiry,
+                    action: stale_disputes,
+                }
+            }),
+        ),
+        Some(Command::Journal {
+            command: JournalCommand::Verify { file },
+        }) => verify_journal(file),
+        None => process(cli),
+    }
+}
 
-    let transaction_file_path = format!("./csvFiles/{}", args[0]);
-    let account_file_path = format!("./csvFiles/{}", "accounts.csv");
-    let failed_txs_file_path = format!("./csvFiles/{}", "failed.csv");
+fn process(cli: Cli) {
+    let mut transaction_file_path = csv_file_path(&cli.transactions.unwrap_or_default());
+    if cli.sort_by_timestamp {
+        let sorted_file_path = format!("{}.sorted", transaction_file_path);
+        sort::sort_by_timestamp(&transaction_file_path, &sorted_file_path, cli.sort_run_size)
+            .expect("Failed at sorting the transactions");
+        transaction_file_path = sorted_file_path;
+    }
     let mut engine = PaymentEngine::new(transaction_file_path);
-    engine
-        .parse_transactions()
+    engine.check_invariants = cli.check_invariants;
+    engine.string_clients = cli.string_clients;
+    engine.amount_parsing = cli.amount_parsing;
+    engine.warn_normalized_amounts = cli.warn_normalized_amounts;
+    engine.compact = cli.compact;
+    engine.history_cap = cli.history_cap;
+    engine.eviction = cli.eviction;
+    if let Some(transaction_index_file) = cli.transaction_index_file {
+        engine
+            .use_disk_transaction_index(csv_file_path(&transaction_index_file))
+            .expect("Failed at creating the transaction index file");
+    }
+    engine.csv_writer_options = CsvWriterOptions {
+        quote_style: cli.quote_style,
+        line_ending: cli.line_ending,
+        delimiter: cli.delimiter as u8,
+    };
+    if !cli.columns.is_empty() {
+        engine.account_columns = Some(cli.columns);
+    }
+    engine.failure_context = cli.failure_context;
+    engine.hold_expiry = hold_expiry(cli.hold_expiry_transactions, cli.hold_expiry_days);
+    if cli.max_chargebacks.is_some() || cli.max_chargeback_ratio.is_some() {
+        engine.chargeback_rule = Some(ChargebackRule {
+            max_chargebacks: cli.max_chargebacks,
+            max_chargeback_ratio: cli.max_chargeback_ratio,
+            action: cli.chargeback_action,
+        });
+    }
+    if let Some(max_score) = cli.max_risk_score {
+        engine.risk_rule = Some(RiskRule {
+            max_score,
+            min_transactions: cli.risk_min_transactions,
+        });
+    }
+    engine.dispute_deadline =
+        hold_expiry(cli.dispute_deadline_transactions, cli.dispute_deadline_days).map(|expiry| {
+            DisputeDeadline {
+                expiry,
+                action: cli.stale_disputes,
+            }
+        });
+    engine.precision_audit = cli.precision_audit;
+    engine.from_timestamp = cli
+        .from
+        .map(|from| timestamp::parse_range_start(&from).expect("invalid --from"));
+    engine.to_timestamp = cli
+        .to
+        .map(|to| timestamp::parse_range_end(&to).expect("invalid --to"));
+    if !cli.only_clients.is_empty() {
+        engine.select_clients(cli.only_clients.iter().map(String::as_str));
+    }
+    if let Some(only_clients_file) = cli.only_clients_file {
+        engine
+            .select_clients_from_file(csv_file_path(&only_clients_file))
+            .expect("Failed at loading the clients to process");
+    }
+    engine.max_failures = cli.max_failures;
+    engine.max_failure_rate = cli.max_failure_rate;
+    engine.failure_mask = FailureMask {
+        amounts: cli.mask_amounts,
+        clients: cli.mask_clients,
+    };
+    if cli.stream_failures {
+        engine
+            .stream_failures_to_file(csv_file_path("failed.csv"), cli.failure_sample)
+            .expect("Failed at creating the failed transactions file");
+    }
+    // before the metadata, which sets the class of the accounts already there.
+    if let Some(resume_accounts) = cli.resume_accounts {
+        engine
+            .merge_account_exports(&[csv_file_path(&resume_accounts)])
+            .expect("Failed at loading the accounts to resume from");
+    }
+    if let Some(clients) = cli.clients {
+        engine
+            .load_client_metadata(csv_file_path(&clients))
+            .expect("Failed at loading the client metadata");
+    }
+    if let Some(fees) = cli.fees {
+        engine
+            .load_fee_schedule(csv_file_path(&fees))
+            .expect("Failed at loading the fee schedule");
+    }
+    if cli.tenants || cli.tenant.is_some() {
+        process_tenants(engine, cli.tenant, cli.sign_key_file);
+        return;
+    }
+    let journal_file = cli.journal.as_ref().map(|journal| csv_file_path(journal));
+    let journal = journal_file.as_ref().map(|journal| {
+        let journal =
+            Arc::new(Journal::create(journal.clone()).expect("Failed at creating the journal"));
+        engine.register_observer(journal.clone());
+        journal
+    });
+    if let Some(threads) = cli.threads {
+        let threads = match threads {
+            0 => shard::default_threads(),
+            threads => threads,
+        };
+        let sequential = cli.verify_determinism.then(|| engine.clone());
+        let reports = engine
+            .parse_transactions_sharded(threads, cli.pin_threads)
+            .expect("Failed at processing transactions");
+        for report in reports.iter() {
+            let core = report
+                .core
+                .map(|core| format!(" on core {}", core))
+                .unwrap_or_default();
+            println!(
+                "shard {}{}: {} rows, {:.0} rows/sec",
+                report.shard,
+                core,
+                report.rows,
+                report.rows_per_sec()
+            );
+        }
+        if let Some(mut sequential) = sequential {
+            sequential
+                .parse_transactions()
+                .expect("Failed at processing transactions");
+            let same = engine
+                .same_exports(&sequential)
+                .expect("comparing the exports failed.");
+            if !same {
+                eprintln!("the sharded exports differ from the sequential ones!");
+                std::process::exit(1);
+            }
+            println!("the sharded exports match the sequential ones!");
+        }
+    } else {
+        let start = match (cli.start_offset, cli.start_line) {
+            (Some(offset), _) => Some(StartPosition::Offset(offset)),
+            (None, Some(line)) => Some(StartPosition::Line(line)),
+            (None, None) => None,
+        };
+        match (start, cli.parse_threads) {
+            (Some(start), _) => engine.parse_transactions_from(start),
+            (None, Some(0)) => engine.parse_transactions_chunked(shard::default_threads()),
+            (None, Some(parse_threads)) => engine.parse_transactions_chunked(parse_threads),
+            (None, None) => engine.parse_transactions(),
+        }
+        .expect("Failed at processing transactions");
+    }
+    if let Some(journal) = journal {
+        journal.flush().expect("writing the journal failed.");
+    }
+    if let (Some(settlement), Some(journal)) = (cli.settlement, &journal_file) {
+        let clients = settlement::export_settlement(journal.clone(), csv_file_path(&settlement))
+            .expect("writing the settlement failed.");
+        println!("{} clients settled!", clients);
+    }
+    if let Some(snapshot) = cli.snapshot {
+        engine
+            .write_snapshot(csv_file_path(&snapshot))
+            .expect("writing the snapshot failed.");
+    }
+    export_engine(&engine, "", &cli.sign_key_file);
+    print_summary(&engine);
+    if let Some(reason) = &engine.aborted {
+        eprintln!("transactions processing aborted: {}!", reason);
+        std::process::exit(ABORTED_EXIT_CODE);
+    }
+    println!("transactions processing complete!")
+}
+
+fn process_tenants(template: PaymentEngine, tenant: Option<String>, sign_key_file: Option<String>) {
+    if let Some(tenant) = &tenant {
+        tenant::validate_tenant(tenant).expect("invalid --tenant");
+    }
+    let failure_mask = template.failure_mask;
+    let csv_writer_options = template.csv_writer_options;
+    let mut engines = TenantEngines::new(template);
+    engines
+        .parse_transactions(tenant.as_deref())
         .expect("Failed at processing transactions");
+    for (tenant, engine) in engines.tenants.iter() {
+        let output_dir = format!("{}/", tenant);
+        std::fs::create_dir_all(csv_file_path(&output_dir))
+            .expect("creating the tenant directory failed.");
+        export_engine(engine, &output_dir, &sign_key_file);
+        println!("tenant {}:", tenant);
+        print_summary(engine);
+    }
+    if !engines.failed_without_tenant.is_empty() {
+        let mut engine = PaymentEngine::default();
+        engine.failed_transactions = engines.failed_without_tenant;
+        engine.failure_mask = failure_mask;
+        engine.csv_writer_options = csv_writer_options;
+        engine
+            .export_failed_txs_to_file(csv_file_path("failed.csv"))
+            .expect("exporting failed transactions to file failed.");
+        println!(
+            "A total of {} transactions without a valid tenant have failed!",
+            engine.failed_transactions.len()
+        );
+    }
+    println!("transactions processing complete!")
+}
+
+// write `accounts.csv`, `failed.csv` and `warnings.csv` to `./csvFiles/<output_dir>`, signing them if a key is
+// given, and `precision.csv` and `flags.csv` when auditing the precision or running rules.
+fn export_engine(engine: &PaymentEngine, output_dir: &str, sign_key_file: &Option<String>) {
+    let account_file_path = csv_file_path(&format!("{}accounts.csv", output_dir));
+    let failed_txs_file_path = csv_file_path(&format!("{}failed.csv", output_dir));
+    let warnings_file_path = csv_file_path(&format!("{}warnings.csv", output_dir));
     engine
-        .export_accounts_to_file(account_file_path)
+        .export_accounts_to_file(account_file_path.clone())
         .expect("exporting account to file failed.");
+    if engine.failure_stream.is_some() {
+        engine
+            .flush_failure_stream()
+            .expect("exporting failed transactions to file failed.");
+    } else {
+        engine
+            .export_failed_txs_to_file(failed_txs_file_path.clone())
+            .expect("exporting failed transactions to file failed.");
+    }
     engine
-        .export_failed_txs_to_file(failed_txs_file_path)
-        .expect("exporting failed transactions to file failed.");
+        .export_warnings_to_file(warnings_file_path.clone())
+        .expect("exporting warnings to file failed.");
+    if engine.precision_audit.is_some() {
+        engine
+            .export_rounding_to_file(csv_file_path(&format!("{}precision.csv", output_dir)))
+            .expect("exporting the precision audit to file failed.");
+    }
+    if engine.has_rules() {
+        engine
+            .export_flags_to_file(csv_file_path(&format!("{}flags.csv", output_dir)))
+            .expect("exporting the flags to file failed.");
+    }
+    if let Some(sign_key_file) = sign_key_file {
+        let key = read_key_file(sign_key_file);
+        for file_path in [
+            &account_file_path,
+            &failed_txs_file_path,
+            &warnings_file_path,
+        ] {
+            signature::sign_file(file_path, &key).expect("signing the exports failed.");
+        }
+    }
+}
+
+fn print_summary(engine: &PaymentEngine) {
     println!("A total of {} accounts were found!", &engine.accounts.len());
     println!(
         "A total of {} transactions have failed!",
-        &engine.failed_transactions.len()
+        engine.failure_count()
     );
-    println!("transactions processing complete!")
+    if !engine.warnings.is_empty() {
+        println!("A total of {} warnings were raised!", engine.warnings.len());
+    }
+    if engine.fees_charged > 0.0 {
+        println!(
+            "A total of {:.4} in fees were charged!",
+            engine.fees_charged
+        );
+    }
+    if engine.released_holds > 0 {
+        println!(
+            "A total of {} expired holds were released!",
+            engine.released_holds
+        );
+    }
+    if !engine.flags.is_empty() {
+        println!(
+            "A total of {} accounts were flagged by the rules!",
+            engine.flags.len()
+        );
+    }
+    if engine.closed_stale_disputes > 0 {
+        println!(
+            "A total of {} stale disputes were closed!",
+            engine.closed_stale_disputes
+        );
+    }
+    if engine.skipped_duplicates > 0 {
+        println!(
+            "A total of {} duplicate transactions were skipped!",
+            engine.skipped_duplicates
+        );
+    }
+    if engine.skipped_clients > 0 {
+        println!(
+            "A total of {} transactions of other clients were skipped!",
+            engine.skipped_clients
+        );
+    }
+    if engine.skipped_out_of_range > 0 {
+        println!(
+            "A total of {} transactions out of the date range were skipped!",
+            engine.skipped_out_of_range
+        );
+    }
+    if let Some(threshold) = engine.precision_audit {
+        let largest = engine
+            .rounding
+            .values()
+            .map(|rounding| rounding.total().abs())
+            .fold(0.0, f64::max);
+        println!(
+            "A total of {} accounts were rounded by more than {}, {:.8} at most!",
+            engine.rounding_over_threshold().len(),
+            threshold,
+            largest
+        );
+    }
+    if let (Some(end_offset), Some(end_line)) = (engine.end_offset, engine.end_line) {
+        println!(
+            "Processing stopped at byte offset {} (line {})!",
+            end_offset, end_line
+        );
+    }
+}
+
+fn query(snapshot: String, client: String, highest: usize) {
+    let engine = PaymentEngine::read_snapshot(csv_file_path(&snapshot))
+        .expect("reading the snapshot failed.");
+    let report = engine
+        .find_client(&client)
+        .and_then(|client_id| engine.client_report(client_id, highest));
+    let report = match report {
+        Some(report) => report,
+        None => {
+            eprintln!("client {} not found!", client);
+            std::process::exit(1);
+        }
+    };
+    let account = &report.account;
+    println!(
+        "client {}: available {:.4}, held {:.4}, total {:.4}, locked {}",
+        client, account.available, account.held, account.total, account.locked
+    );
+    println!("{} open disputes:", report.open_disputes.len());
+    for transaction in report.open_disputes.iter() {
+        print_transaction(transaction);
+    }
+    println!("transactions with the highest IDs:");
+    for transaction in report.highest.iter() {
+        print_transaction(transaction);
+    }
+}
+
+fn print_transaction(transaction: &Transaction) {
+    let flags = match (
+        transaction.disputed,
+        transaction.charged_back,
+        transaction.captured,
+    ) {
+        (true, _, _) => ", disputed",
+        (_, true, _) => ", charged back",
+        (_, _, true) => ", captured",
+        _ => "",
+    };
+    println!(
+        "  tx {}: {:?} of {:.4}{}",
+        transaction.tx, transaction.r#type, transaction.amount, flags
+    );
+}
+
+fn merge(inputs: Vec<String>, output: String) {
+    let input_file_paths: Vec<String> = inputs.iter().map(|input| csv_file_path(input)).collect();
+    let mut engine = PaymentEngine::default();
+    let conflicts = engine
+        .merge_account_exports(&input_file_paths)
+        .expect("Failed at merging account exports");
+    engine
+        .export_accounts_to_file(csv_file_path(&output))
+        .expect("exporting account to file failed.");
+    for client in conflicts.iter() {
+        eprintln!(
+            "Client {} is locked in some exports but not in others; kept it locked.",
+            client
+        );
+    }
+    println!(
+        "A total of {} accounts were merged!",
+        &engine.accounts.len()
+    );
+    println!(
+        "A total of {} accounts have conflicting locked flags!",
+        conflicts.len()
+    );
+    println!("accounts merge complete!")
+}
+
+fn reconcile(transactions: String, statement: String, output: String) {
+    let mut engine = PaymentEngine::new(csv_file_path(&transactions));
+    engine
+        .parse_transactions()
+        .expect("Failed at processing transactions");
+    let breaks = engine
+        .reconcile(csv_file_path(&statement))
+        .expect("Failed at reconciling against the statement");
+    PaymentEngine::export_breaks_to_file(&breaks, csv_file_path(&output))
+        .expect("exporting breaks to file failed.");
+    println!("A total of {} breaks were found!", breaks.len());
+    println!("reconciliation complete!")
+}
+
+fn bench(transactions: String) {
+    let mut engine = PaymentEngine::new(csv_file_path(&transactions));
+    let report = engine.bench().expect("Failed at processing transactions");
+    println!("rows: {}", report.rows);
+    println!("rows/sec: {:.0}", report.rows_per_sec());
+    println!("parsing: {:?}", report.parsing);
+    println!("applying: {:?}", report.applying);
+    match report.peak_rss_kb {
+        Some(peak_rss_kb) => println!("peak RSS: {} kB", peak_rss_kb),
+        None => println!("peak RSS: unknown"),
+    }
+}
+
+fn repl(transactions: String, string_clients: bool) {
+    let mut engine = PaymentEngine::new(csv_file_path(&transactions));
+    engine.string_clients = string_clients;
+    engine
+        .parse_transactions()
+        .expect("Failed at processing transactions");
+    println!(
+        "Loaded {} accounts, {} transactions have failed. Type `help` for the list of commands.",
+        engine.accounts.len(),
+        engine.failed_transactions.len()
+    );
+    repl::run(&mut engine).expect("reading the repl input failed.");
+}
+
+fn statement(
+    transactions: String,
+    from: String,
+    to: String,
+    output: String,
+    text: bool,
+    clients: Option<String>,
+) {
+    let from = timestamp::parse_range_start(&from).expect("invalid --from");
+    let to = timestamp::parse_range_end(&to).expect("invalid --to");
+    let mut engine = PaymentEngine::new(csv_file_path(&transactions));
+    if let Some(clients) = clients {
+        engine
+            .load_client_metadata(csv_file_path(&clients))
+            .expect("Failed at loading the client metadata");
+    }
+    let statements = engine
+        .statements(from, to)
+        .expect("Failed at processing transactions");
+    if text {
+        std::fs::write(
+            csv_file_path(&output),
+            engine.render_statements_text(&statements, from, to),
+        )
+        .expect("writing statements to file failed.");
+    } else {
+        PaymentEngine::export_statements_to_file(&statements, csv_file_path(&output))
+            .expect("exporting statements to file failed.");
+    }
+    println!("A total of {} statements were produced!", statements.len());
+    println!("statements complete!")
+}
+
+// the files of `./csvFiles` a client is forgotten in.
+struct ForgetFiles {
+    accounts: String,
+    failed: String,
+    clients: Option<String>,
+    warnings: String,
+    flags: String,
+    snapshot: Option<String>,
+    journal: Option<String>,
+}
+
+fn forget(client: String, files: ForgetFiles, delimiter: char) {
+    // the entries of a journal are hash chained, removing the client's would break the chain.
+    if let Some(journal) = files
+        .journal
+        .filter(|journal| Path::new(&csv_file_path(journal)).exists())
+    {
+        eprintln!(
+            "client {} can't be forgotten while the journal {} exists!",
+            client, journal
+        );
+        std::process::exit(1);
+    }
+    let options = CsvWriterOptions {
+        delimiter: delimiter as u8,
+        ..Default::default()
+    };
+    if let Some(snapshot) = files.snapshot {
+        let mut engine = PaymentEngine::read_snapshot(csv_file_path(&snapshot))
+            .expect("Failed at reading the snapshot");
+        let found = engine
+            .find_client(&client)
+            .map(|id| engine.forget_client(id))
+            .transpose()
+            .expect("forgetting the client in the snapshot failed.");
+        if found.is_some() {
+            engine
+                .write_snapshot(csv_file_path(&snapshot))
+                .expect("writing the snapshot failed.");
+        }
+    }
+    let anonymized =
+        forget::anonymize_client_in_file(csv_file_path(&files.accounts), &client, &options)
+            .expect("anonymizing the accounts export failed.");
+    if let Some(clients) = files.clients {
+        forget::anonymize_client_in_file(
+            csv_file_path(&clients),
+            &client,
+            &CsvWriterOptions::default(),
+        )
+        .expect("anonymizing the client metadata failed.");
+    }
+    let removed =
+        forget::remove_client_from_failed_file(csv_file_path(&files.failed), &client, &options)
+            .expect("removing the failed transactions failed.");
+    let warnings = csv_file_path(&files.warnings);
+    if Path::new(&warnings).exists() {
+        forget::remove_client_from_warnings_file(warnings, &client, &options)
+            .expect("removing the warnings failed.");
+    }
+    let flags = csv_file_path(&files.flags);
+    if Path::new(&flags).exists() {
+        forget::remove_client_from_file(flags, &client, &options)
+            .expect("removing the flags failed.");
+    }
+    println!("A total of {} account rows were anonymized!", anonymized);
+    println!("A total of {} failed transactions were removed!", removed);
+    println!("client {} forgotten!", client)
+}
+
+fn verify(file: String, key_file: String) {
+    let key = read_key_file(&key_file);
+    let valid = signature::verify_file(&csv_file_path(&file), &key)
+        .expect("reading the signed file failed.");
+    if valid {
+        println!("{} signature is valid!", file);
+    } else {
+        println!("{} signature is NOT valid!", file);
+        std::process::exit(1);
+    }
+}
+
+fn import(format: ImportFormat, file: String, client: Option<u32>, first_tx: u64) {
+    let contents = std::fs::read_to_string(csv_file_path(&file)).expect("reading the file failed.");
+    let client = client.map(|client| client.to_string());
+    let transactions: Vec<ImportedTransaction> = match format {
+        ImportFormat::Iso20022 => import::iso20022::parse(&contents, client.as_deref()),
+        #[cfg(feature = "fix")]
+        ImportFormat::Fix => import::fix::parse(&contents, client.as_deref()),
+        #[cfg(feature = "personal-finance")]
+        ImportFormat::Ofx => import::ofx::parse(&contents, client.as_deref()),
+        #[cfg(feature = "personal-finance")]
+        ImportFormat::Qif => import::qif::parse(&contents, client.as_deref()),
+    }
+    .expect("Failed at importing the file");
+    let mut engine = PaymentEngine::default();
+    engine.string_clients = client.is_none();
+    engine.apply_imported_transactions(&transactions, first_tx);
+    export_engine(&engine, "", &None);
+    println!(
+        "A total of {} transactions were imported!",
+        transactions.len()
+    );
+    print_summary(&engine);
+    println!("import complete!")
+}
+
+// the expiry after `transactions` more transactions or `days` days, if either is given.
+fn hold_expiry(transactions: Option<u64>, days: Option<u64>) -> Option<HoldExpiry> {
+    (transactions.is_some() || days.is_some()).then_some(HoldExpiry { transactions, days })
+}
+
+fn close_day(
+    transactions: String,
+    date: String,
+    previous: Option<String>,
+    fees: Option<String>,
+    hold_expiry: Option<HoldExpiry>,
+    dispute_deadline: Option<DisputeDeadline>,
+) {
+    let previous_snapshot = previous
+        .map(|previous| csv_file_path(&format!("{}/{}", previous, close_day::SNAPSHOT_FILE)));
+    let engine = close_day::close_day(
+        csv_file_path(&transactions),
+        &date,
+        &csv_file_path(&date),
+        previous_snapshot,
+        fees.map(|fees| csv_file_path(&fees)),
+        hold_expiry,
+        dispute_deadline,
+    )
+    .expect("Failed at closing the day");
+    export_engine(&engine, &format!("{}/", date), &None);
+    print_summary(&engine);
+    println!("day {} closed!", date);
+}
+
+fn verify_journal(file: String) {
+    match journal::verify_journal(csv_file_path(&file)) {
+        Ok(entries) => println!("{} is intact, {} entries verified!", file, entries),
+        Err(e) => {
+            println!("{} has been tampered with: {}", file, e);
+            std::process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,48 @@
+use crate::account::Account;
+use crate::hold::StaleDisputeAction;
+use crate::payment_engine::Transaction;
+use crate::rules::Flag;
+use csv::StringRecord;
+use std::fmt::Debug;
+
+// Callbacks invoked by the engine while it processes transactions, registered with
+// `PaymentEngine::register_observer`. Every callback defaults to doing nothing so observers only
+// implement the events they care about; they take `&self`, observers that keep state need
+// interior mutability.
+pub trait EngineObserver: Debug + Send + Sync {
+    // the transaction has been applied, `account` is its account after the change.
+    fn on_transaction_applied(&self, _transaction: &Transaction, _account: &Account) {}
+
+    // the record has been rejected, it is also written to the failed transactions.
+    fn on_transaction_failed(&self, _record: &StringRecord, _error: &anyhow::Error) {}
+
+    // the fee of a deposit or withdrawal has been charged; `fee` is the linked transaction, with the
+    // ID of the one it was charged on.
+    fn on_fee_charged(&self, _fee: &Transaction, _account: &Account) {}
+
+    // the authorization `hold` has expired and its funds been released back to available.
+    fn on_hold_released(&self, _hold: &Transaction, _account: &Account) {}
+
+    // the dispute of `transaction` has been open past the dispute deadline and closed by `action`,
+    // without a resolve or chargeback record.
+    fn on_stale_dispute_closed(
+        &self,
+        _transaction: &Transaction,
+        _action: StaleDisputeAction,
+        _account: &Account,
+    ) {
+    }
+
+    // a rule has flagged an account for review.
+    fn on_account_flagged(&self, _flag: &Flag) {}
+
+    // a chargeback, or a rule, has just locked the account.
+    fn on_account_locked(&self, _account: &Account) {}
+
+    // a transaction of `account` is now under dispute.
+    fn on_dispute_opened(&self, _dispute: &Transaction, _account: &Account) {}
+
+    // with string clients, `client` is the number standing for `label`; called for every label
+    // the engine already has when the observer is registered, and for each new one after.
+    fn on_client_labeled(&self, _client: u32, _label: &str) {}
+}
@@ -0,0 +1,156 @@
+use crate::account::float_four_digit_serialize;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::str::FromStr;
+
+// When the fields of the exports are quoted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuoteStyle {
+    Always,
+    // only the fields holding a delimiter, quote or line ending.
+    #[default]
+    Necessary,
+    NonNumeric,
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+// How the accounts and failed transactions exports are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CsvWriterOptions {
+    pub quote_style: QuoteStyle,
+    pub line_ending: LineEnding,
+    pub delimiter: u8,
+}
+
+impl Default for CsvWriterOptions {
+    fn default() -> Self {
+        Self {
+            quote_style: QuoteStyle::default(),
+            line_ending: LineEnding::default(),
+            delimiter: b',',
+        }
+    }
+}
+
+impl CsvWriterOptions {
+    pub(crate) fn writer_builder(&self) -> csv::WriterBuilder {
+        let mut builder = csv::WriterBuilder::new();
+        builder
+            .quote_style(match self.quote_style {
+                QuoteStyle::Always => csv::QuoteStyle::Always,
+                QuoteStyle::Necessary => csv::QuoteStyle::Necessary,
+                QuoteStyle::NonNumeric => csv::QuoteStyle::NonNumeric,
+                QuoteStyle::Never => csv::QuoteStyle::Never,
+            })
+            .terminator(match self.line_ending {
+                LineEnding::Lf => csv::Terminator::Any(b'\n'),
+                LineEnding::Crlf => csv::Terminator::CRLF,
+            })
+            .delimiter(self.delimiter);
+        builder
+    }
+}
+
+// A column of the accounts export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountColumn {
+    Client,
+    Available,
+    Held,
+    Total,
+    Locked,
+    Name,
+    Tier,
+    Country,
+    Class,
+    // the number of disputed transactions not resolved or charged back yet.
+    OpenDisputes,
+    // the risk score of the account over the run, from 0 to 100.
+    RiskScore,
+}
+
+impl AccountColumn {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AccountColumn::Client => "client",
+            AccountColumn::Available => "available",
+            AccountColumn::Held => "held",
+            AccountColumn::Total => "total",
+            AccountColumn::Locked => "locked",
+            AccountColumn::Name => "name",
+            AccountColumn::Tier => "tier",
+            AccountColumn::Country => "country",
+            AccountColumn::Class => "class",
+            AccountColumn::OpenDisputes => "open_disputes",
+            AccountColumn::RiskScore => "risk_score",
+        }
+    }
+}
+
+// A field of an exported account, written like the fields of the default layout.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub(crate) enum Cell<'a> {
+    Text(&'a str),
+    Label(String),
+    Amount(#[serde(serialize_with = "float_four_digit_serialize")] f32),
+    Flag(bool),
+    Count(usize),
+}
+
+impl FromStr for AccountColumn {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "client" => Ok(AccountColumn::Client),
+            "available" => Ok(AccountColumn::Available),
+            "held" => Ok(AccountColumn::Held),
+            "total" => Ok(AccountColumn::Total),
+            "locked" => Ok(AccountColumn::Locked),
+            "name" => Ok(AccountColumn::Name),
+            "tier" => Ok(AccountColumn::Tier),
+            "country" => Ok(AccountColumn::Country),
+            "class" => Ok(AccountColumn::Class),
+            "open_disputes" => Ok(AccountColumn::OpenDisputes),
+            "risk_score" => Ok(AccountColumn::RiskScore),
+            _ => Err(anyhow!("unknown account column {}", s)),
+        }
+    }
+}
+
+impl FromStr for QuoteStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "always" => Ok(QuoteStyle::Always),
+            "necessary" => Ok(QuoteStyle::Necessary),
+            "non-numeric" => Ok(QuoteStyle::NonNumeric),
+            "never" => Ok(QuoteStyle::Never),
+            _ => Err(anyhow!("unknown quote style {}", s)),
+        }
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            _ => Err(anyhow!("unknown line ending {}", s)),
+        }
+    }
+}
@@ -1,29 +1,173 @@
-use crate::account::Account;
+use crate::account::{float_four_digit_serialize, Account, EvictionPolicy};
+use crate::accounts::Accounts;
+use crate::amount::{AmountParsing, AMOUNT_FIELD};
+use crate::client::ClientMetadata;
+use crate::compact::{self, CompactIndex};
+#[cfg(feature = "fs")]
+use crate::failure::FailureStream;
+use crate::failure::{FailedTransaction, FailureMask};
+use crate::fast_parse;
+use crate::fee::{self, FeeSchedule};
+use crate::handler::TransactionHandler;
+use crate::hold::{DisputeDeadline, HoldExpiry, OpenHolds};
+use crate::observer::EngineObserver;
+use crate::output::{AccountColumn, Cell, CsvWriterOptions};
+use crate::precision::{self, Rounding};
+use crate::rules::{AccountStats, ChargebackRule, Flag, RiskRule};
+use crate::warning::{Severity, Warning};
 use anyhow::{anyhow, Result};
 use csv::StringRecord;
 use serde::{Deserialize, Deserializer, Serialize};
-use std::collections::BTreeMap;
-use std::error::Error;
+use std::collections::{BTreeMap, HashMap, HashSet};
+#[cfg(feature = "fs")]
 use std::fs::File;
-use std::io::BufReader;
+#[cfg(feature = "fs")]
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::io::{Read, Write};
+use std::sync::Arc;
+#[cfg(feature = "fs")]
+use std::sync::Mutex;
+
+// the columns of a transactions file, read by position.
+const KNOWN_COLUMNS: [&str; 6] = [
+    "type",
+    "client",
+    "tx",
+    "amount",
+    "timestamp",
+    "idempotency_key",
+];
+
+// records read before the failure rate is checked, so a few early failures don't abort a run.
+const MIN_RECORDS_FOR_FAILURE_RATE: usize = 1000;
 
 #[derive(Debug, Clone, Serialize, Default)]
 pub struct PaymentEngine {
     // (client, account)
-    pub(crate) accounts: BTreeMap<u16, Account>,
+    pub accounts: Accounts,
+    // (client, metadata)
+    pub clients: BTreeMap<u32, ClientMetadata>,
     // (transaction_id, transaction)
-    pub(crate) failed_transactions: Vec<String>,
-    input_file_path: String,
+    pub failed_transactions: Vec<FailedTransaction>,
+    pub(crate) input_file_path: String,
+    // verify the account invariants after every transaction.
+    pub check_invariants: bool,
+    // fields redacted in the failed transactions export.
+    pub failure_mask: FailureMask,
+    #[serde(skip)]
+    pub(crate) observers: Vec<Arc<dyn EngineObserver>>,
+    // (custom transaction type, handler)
+    #[serde(skip)]
+    pub(crate) handlers: BTreeMap<String, Arc<dyn TransactionHandler>>,
+    // read the client column as an opaque string, e.g. a UUID, instead of a number.
+    pub string_clients: bool,
+    // (opaque client, client) of the string clients; the accounts are keyed by the numbers
+    // given to the opaque clients in order of appearance.
+    pub(crate) client_ids: BTreeMap<String, u32>,
+    // opaque client of each client number.
+    pub(crate) client_labels: Vec<String>,
+    // (idempotency key, client) of the transactions applied so far.
+    pub(crate) applied_idempotency_keys: HashMap<String, u32>,
+    // transactions skipped because their idempotency key was already applied.
+    pub skipped_duplicates: usize,
+    // keep the deposits and withdrawals in `transaction_index` instead of the accounts, cutting
+    // the memory of engines with many clients.
+    pub compact: bool,
+    // (transaction_id, transaction) of every client, in compact mode.
+    #[serde(skip)]
+    pub(crate) transaction_index: CompactIndex,
+    // most transactions kept in the history of each account, unbounded if unset.
+    pub history_cap: Option<usize>,
+    // which transactions leave the history first once it holds more than `history_cap`.
+    pub eviction: EvictionPolicy,
+    // how the amount column is read.
+    pub amount_parsing: AmountParsing,
+    // raise a warning for every amount `amount_parsing` normalized.
+    pub warn_normalized_amounts: bool,
+    // quoting, line ending and delimiter of the accounts and failed transactions exports.
+    pub csv_writer_options: CsvWriterOptions,
+    // columns of the accounts export, in order, instead of the default layout.
+    pub account_columns: Option<Vec<AccountColumn>>,
+    // keep the untrimmed input line of every failure, and export it with its line and byte offset.
+    pub failure_context: bool,
+    // conditions worth reporting that don't fail their record.
+    pub warnings: Vec<Warning>,
+    // stop processing a file once more transactions than this have failed.
+    pub max_failures: Option<usize>,
+    // stop processing a file once more than this fraction of its records have failed.
+    pub max_failure_rate: Option<f64>,
+    // why the processing stopped before the end of the file, if it did.
+    pub aborted: Option<String>,
+    // byte offset and line where the processing of the file stopped, to resume from.
+    pub end_offset: Option<u64>,
+    pub end_line: Option<u64>,
+    // write the failures to the failed transactions export as they are recorded, keeping only
+    // the first ones in `failed_transactions`.
+    #[cfg(feature = "fs")]
+    #[serde(skip)]
+    pub failure_stream: Option<FailureStream>,
+    // failures streamed to the export without being kept in `failed_transactions`.
+    pub dropped_failures: usize,
+    // track the rounding of every account, reporting those whose total rounding is larger than
+    // this.
+    pub precision_audit: Option<f64>,
+    // (client, rounding) of every account, when auditing the precision.
+    #[serde(skip)]
+    pub rounding: BTreeMap<u32, Rounding>,
+    // clients whose transactions are processed, as written in the input; all of them if unset.
+    pub selected_clients: Option<HashSet<String>>,
+    // records skipped for not being of a selected client.
+    pub skipped_clients: usize,
+    // apply only the transactions with a timestamp in this range, both inclusive, if either is
+    // set.
+    pub from_timestamp: Option<u64>,
+    pub to_timestamp: Option<u64>,
+    // transactions skipped for a timestamp out of that range, or none.
+    pub skipped_out_of_range: usize,
+    // fees charged on the deposits and withdrawals, if any.
+    pub fee_schedule: Option<FeeSchedule>,
+    // sum of the fees charged.
+    pub fees_charged: f64,
+    // release the authorizations still not captured after a while, if set.
+    pub hold_expiry: Option<HoldExpiry>,
+    #[serde(skip)]
+    pub(crate) open_authorizations: OpenHolds,
+    // authorizations released by `hold_expiry`.
+    pub released_holds: usize,
+    // resolve or charge back the disputes still open after a while, if set.
+    pub dispute_deadline: Option<DisputeDeadline>,
+    #[serde(skip)]
+    pub(crate) open_disputes: OpenHolds,
+    // disputes closed by `dispute_deadline`.
+    pub closed_stale_disputes: usize,
+    // lock or flag the accounts with too many charge backs, if set.
+    pub chargeback_rule: Option<ChargebackRule>,
+    // flag the accounts whose risk score is too high, if set.
+    pub risk_rule: Option<RiskRule>,
+    // (client, stats) of every account, when a rule or the risk score column needs them.
+    #[serde(skip)]
+    pub account_stats: BTreeMap<u32, AccountStats>,
+    // the accounts flagged by the rules, in the order they were.
+    pub flags: Vec<Flag>,
+    // (client, rule) of every flag, to flag an account once per rule.
+    #[serde(skip)]
+    pub(crate) flagged: HashSet<(u32, &'static str)>,
 }
 
 #[derive(Debug, Clone, Serialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
-pub(crate) enum TransactionType {
+pub enum TransactionType {
     Deposit,
     Withdrawal,
     Dispute,
     Resolve,
     ChargeBack,
+    // reserves funds into held until it is captured.
+    Authorize,
+    // withdraws funds of an authorization and releases the rest.
+    Capture,
+    // reverses a charge back, won back by the merchant.
+    Representment,
     Unknown(String),
 }
 
@@ -35,122 +179,809 @@ impl Default for TransactionType {
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Transaction {
-    pub(crate) r#type: TransactionType,
-    pub(crate) client: u16,
-    pub(crate) tx: u32,
+    pub r#type: TransactionType,
+    pub client: u32,
+    pub tx: u64,
+    #[serde(default)]
+    pub amount: f32,
+    // optional unix timestamp, in seconds.
     #[serde(default)]
-    pub(crate) amount: f32,
+    pub timestamp: Option<u64>,
+    // optional key identifying the transaction across deliveries, applied at most once.
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
     #[serde(skip_serializing, skip_deserializing)]
     pub disputed: bool,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub charged_back: bool,
+    // an authorization that has been captured or released, its hold is gone.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub captured: bool,
+    // what the capture of an authorization withdrew.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub captured_amount: f32,
+    // the fee charged with the transaction, if any.
+    #[serde(skip_serializing, skip_deserializing)]
+    pub fee: f32,
+}
+
+impl Transaction {
+    // an authorization not captured yet, whose amount is still held.
+    pub fn holds_funds(&self) -> bool {
+        self.r#type == TransactionType::Authorize && !self.captured
+    }
+}
+
+// an exported account, joined with its client metadata once a clients file is loaded.
+#[derive(Debug, Serialize)]
+struct AccountRow<'a> {
+    client: String,
+    #[serde(serialize_with = "float_four_digit_serialize")]
+    available: f32,
+    #[serde(serialize_with = "float_four_digit_serialize")]
+    held: f32,
+    #[serde(serialize_with = "float_four_digit_serialize")]
+    total: f32,
+    locked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tier: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    country: Option<&'a str>,
+}
+
+// The exports of the engine held in memory, as the files the binary writes them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Exports {
+    pub accounts: String,
+    pub failed_transactions: String,
+    pub warnings: String,
+    pub flags: String,
 }
 
 impl PaymentEngine {
-    pub(crate) fn new(input_file_path: String) -> Self {
+    pub fn new(input_file_path: String) -> Self {
         Self {
             input_file_path,
             ..Default::default()
         }
     }
 
-    fn new_file_buff_reader(&self) -> Result<csv::Reader<BufReader<File>>> {
-        let file = File::open(self.input_file_path.clone())?;
-        let buff_file_reader = BufReader::new(file);
-        let csv_reader = csv::ReaderBuilder::new()
+    #[cfg(feature = "fs")]
+    pub(crate) fn input_file_path(&self) -> &str {
+        &self.input_file_path
+    }
+
+    pub(crate) fn csv_reader_builder() -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
             .has_headers(true)
             .flexible(true)
             .trim(csv::Trim::All)
-            .delimiter(b',')
-            .from_reader(buff_file_reader);
+            .delimiter(b',');
+        builder
+    }
+
+    #[cfg(feature = "fs")]
+    pub(crate) fn new_file_buff_reader(&self) -> Result<csv::Reader<BufReader<File>>> {
+        let file = File::open(self.input_file_path.clone())?;
+        let buff_file_reader = BufReader::new(file);
+        let csv_reader = PaymentEngine::csv_reader_builder().from_reader(buff_file_reader);
+        Ok(csv_reader)
+    }
+
+    // the reader of the hot paths, which trim the records with `fast_parse::trim_record` as they
+    // read them instead of copying every record.
+    #[cfg(feature = "fs")]
+    pub(crate) fn new_untrimmed_file_reader(&self) -> Result<csv::Reader<BufReader<File>>> {
+        let file = File::open(self.input_file_path.clone())?;
+        let csv_reader = PaymentEngine::csv_reader_builder()
+            .trim(csv::Trim::None)
+            .from_reader(BufReader::new(file));
         Ok(csv_reader)
     }
 
     // parse the transactions file and load it into a btree map.
+    #[cfg(feature = "fs")]
     pub fn parse_transactions(&mut self) -> Result<()> {
-        let mut csv_reader = self.new_file_buff_reader()?;
+        let mut csv_reader = self.new_untrimmed_file_reader()?;
+        self.parse_records(&mut csv_reader);
+        Ok(())
+    }
+
+    // same as `parse_transactions` but reads the CSV text from memory, for embedders without a
+    // file system.
+    pub fn parse_transactions_from_str(&mut self, transactions: &str) {
+        let mut csv_reader = PaymentEngine::csv_reader_builder()
+            .trim(csv::Trim::None)
+            .from_reader(transactions.as_bytes());
+        self.parse_records(&mut csv_reader);
+    }
 
-        for record in csv_reader.records() {
-            match record {
-                Ok(_record) => {
-                    match _record.deserialize::<Transaction>(None) {
+    pub(crate) fn parse_records<R: Read>(&mut self, csv_reader: &mut csv::Reader<R>) {
+        self.check_headers(csv_reader);
+        let failed_before = self.failure_count();
+        let mut read = 0;
+        let mut _record = StringRecord::new();
+        loop {
+            match csv_reader.read_record(&mut _record) {
+                Ok(false) => break,
+                Ok(true) => {
+                    fast_parse::trim_record(&mut _record);
+                    if self.skips_client(&_record) {
+                        continue;
+                    }
+                    match self.deserialize_record(&_record) {
                         Ok(deserialized_record) => {
-                            if deserialized_record.amount == 0.0
-                                && (deserialized_record.r#type == TransactionType::Deposit
-                                    || deserialized_record.r#type == TransactionType::Withdrawal)
-                            {
-                                self.failed_transactions
-                                    .push(PaymentEngine::formatted_bad_record(
-                                        &_record,
-                                        anyhow!(
-                                            "{:?} transaction must be above zero",
-                                            deserialized_record.r#type
-                                        )
-                                        .into(),
-                                    ));
-                                // return Err(anyhow!(
-                                //     "{:?} transaction must be above zero",
-                                //     deserialized_record.r#type
-                                // ));
-                            }
-                            let account = self
-                                .accounts
-                                .entry(deserialized_record.client)
-                                .or_insert(Account {
-                                    client: deserialized_record.client,
-                                    available: 0.0,
-                                    held: 0.0,
-                                    total: 0.0,
-                                    locked: false,
-                                    transactions: Default::default(),
-                                });
-                            match account.process_transaction(&deserialized_record) {
-                                Ok(_) => {}
-                                Err(e) => {
-                                    self.failed_transactions.push(
-                                        PaymentEngine::formatted_bad_record(&_record, e.into()),
-                                    );
-                                }
-                            }
-                            if account.process_transaction(&deserialized_record).is_err() {}
-                        }
-                        Err(e) => {
-                            self.failed_transactions
-                                .push(PaymentEngine::formatted_bad_record(&_record, e.into()));
+                            self.apply_transaction(&_record, &deserialized_record)
                         }
+                        Err(e) => self.record_failure(&_record, e),
                     };
                 }
                 Err(e) => eprintln!("Could not read line: {}", e),
             }
+            read += 1;
+            let failed = self.failure_count() - failed_before;
+            if let Some(reason) = self.failure_limit_reached(read, failed) {
+                self.aborted = Some(reason);
+                break;
+            }
         }
+        let position = csv_reader.position();
+        self.end_offset = Some(position.byte());
+        self.end_line = Some(position.line());
+    }
+
+    // why the processing should stop after `failed` failures in `read` records, if it should.
+    pub(crate) fn failure_limit_reached(&self, read: usize, failed: usize) -> Option<String> {
+        if let Some(max_failures) = self.max_failures {
+            if failed > max_failures {
+                return Some(format!(
+                    "more than {} transactions have failed",
+                    max_failures
+                ));
+            }
+        }
+        if let Some(max_failure_rate) = self.max_failure_rate {
+            let rate = failed as f64 / read as f64;
+            if read >= MIN_RECORDS_FOR_FAILURE_RATE && rate > max_failure_rate {
+                return Some(format!(
+                    "{:.2}% of the first {} records have failed",
+                    rate * 100.0,
+                    read
+                ));
+            }
+        }
+        None
+    }
 
+    // warn about the columns the engine doesn't know, they are ignored.
+    pub(crate) fn check_headers<R: Read>(&mut self, csv_reader: &mut csv::Reader<R>) {
+        let mut headers = match csv_reader.headers() {
+            Ok(headers) => headers.clone(),
+            Err(_) => return,
+        };
+        fast_parse::trim_record(&mut headers);
+        for header in headers.iter().skip(KNOWN_COLUMNS.len()) {
+            self.record_warning(
+                Severity::Warning,
+                None,
+                format!("Unknown column {} ignored.", header),
+            );
+        }
+    }
+
+    // apply a deserialized transaction to its account, failures are recorded against the raw record.
+    pub(crate) fn apply_transaction(&mut self, record: &StringRecord, transaction: &Transaction) {
+        if !self.in_date_range(transaction) {
+            self.skipped_out_of_range += 1;
+            return;
+        }
+        if let Some(idempotency_key) = &transaction.idempotency_key {
+            if self.applied_idempotency_keys.contains_key(idempotency_key) {
+                self.skipped_duplicates += 1;
+                self.record_warning(
+                    Severity::Warning,
+                    Some(record),
+                    format!(
+                        "Duplicate skipped; idempotency key {} was already applied.",
+                        idempotency_key
+                    ),
+                );
+                return;
+            }
+        }
+        self.expire_holds(transaction);
+        if transaction.amount == 0.0
+            && (transaction.r#type == TransactionType::Deposit
+                || transaction.r#type == TransactionType::Withdrawal)
+        {
+            self.record_failure(
+                record,
+                anyhow!("{:?} transaction must be above zero", transaction.r#type),
+            );
+            // return Err(anyhow!(
+            //     "{:?} transaction must be above zero",
+            //     transaction.r#type
+            // ));
+        }
+        let class = self
+            .clients
+            .get(&transaction.client)
+            .map(|metadata| metadata.class)
+            .unwrap_or_default();
+        if self.compact && !self.accounts.is_flat() {
+            self.accounts.make_flat();
+        }
+        let account = self
+            .accounts
+            .get_or_insert_with(transaction.client, || Account {
+                client: transaction.client,
+                available: 0.0,
+                held: 0.0,
+                total: 0.0,
+                locked: false,
+                transactions: Default::default(),
+                class,
+                evicted_up_to: None,
+                locked_by_rule: false,
+                chargebacks: 0,
+            });
+        if self.compact
+            && transaction.r#type != TransactionType::Deposit
+            && transaction.r#type != TransactionType::Withdrawal
+            && transaction.r#type != TransactionType::Authorize
+        {
+            if let Err(e) = compact::restore(&self.transaction_index, account, transaction.tx) {
+                self.record_failure(record, e);
+                return;
+            }
+        }
+        // the fee is checked first so the transaction isn't applied without it.
+        let fee = match &self.fee_schedule {
+            Some(fee_schedule) if !account.locked => fee_schedule.fee(transaction, account.class),
+            _ => None,
+        };
+        if let Some(fee) = fee {
+            if !account.covers_fee(transaction, fee) {
+                self.record_failure(
+                    record,
+                    anyhow!(
+                        "Can't apply the {:?}; insufficient funds for its fee of {:.4}.",
+                        transaction.r#type,
+                        fee
+                    ),
+                );
+                return;
+            }
+        }
+        let was_locked = account.locked;
+        let total_before = account.total;
+        let processed = match &transaction.r#type {
+            TransactionType::Unknown(r#type) => match self.handlers.get(r#type) {
+                Some(handler) => account.process_custom_transaction(handler.as_ref(), transaction),
+                None => account.process_transaction(transaction),
+            },
+            _ => account.process_transaction(transaction),
+        };
+        // before the original transaction of a charge back is compacted or evicted.
+        let requested = match (self.precision_audit, &processed) {
+            (Some(_), Ok(())) => precision::requested_change(transaction, account),
+            _ => None,
+        };
+        let original_amount = match transaction.r#type {
+            TransactionType::ChargeBack | TransactionType::Representment => account
+                .transactions
+                .get(&transaction.tx)
+                .map(|original| original.amount),
+            _ => None,
+        };
+        let fee = fee.filter(|_| processed.is_ok()).map(|fee| {
+            account.charge_fee(fee);
+            if let Some(charged) = account.transactions.get_mut(&transaction.tx) {
+                charged.fee += fee;
+            }
+            fee::fee_transaction(transaction, fee)
+        });
+        if processed.is_ok() {
+            if let Some(idempotency_key) = &transaction.idempotency_key {
+                self.applied_idempotency_keys
+                    .insert(idempotency_key.clone(), transaction.client);
+            }
+            for observer in self.observers.iter() {
+                observer.on_transaction_applied(transaction, account);
+                if transaction.r#type == TransactionType::Dispute {
+                    observer.on_dispute_opened(transaction, account);
+                }
+                if let Some(fee) = &fee {
+                    observer.on_fee_charged(fee, account);
+                }
+                if account.locked && !was_locked {
+                    observer.on_account_locked(account);
+                }
+            }
+        }
+        if let Some(fee) = &fee {
+            self.fees_charged += fee.amount as f64;
+        }
+        let violation = if !self.check_invariants {
+            None
+        } else if self.compact {
+            // the locked flag can't be checked against the history of a single transaction.
+            account.check_balances().err()
+        } else {
+            account.check_invariants().err()
+        };
+        let stored = if self.compact {
+            compact::compact(&mut self.transaction_index, account).err()
+        } else {
+            if let Some(history_cap) = self.history_cap {
+                account.evict(history_cap, self.eviction);
+            }
+            None
+        };
+        let applied = processed.is_ok();
+        if let Some(requested) = requested {
+            // the fee is not part of the change the transaction asks for.
+            let fee = fee.map(|fee| fee.amount).unwrap_or_default();
+            self.audit_rounding(record, transaction, total_before - fee, requested);
+        }
+        if let Err(e) = processed {
+            self.record_failure(record, e);
+        }
+        if let Some(e) = violation {
+            self.record_failure(record, e);
+        }
+        if let Some(e) = stored {
+            self.record_failure(record, e);
+        }
+        if applied {
+            self.place_hold(transaction);
+            self.apply_rules(Some(record), transaction, original_amount);
+        }
+    }
+
+    // keep the history in a hash index in the file at `transaction_index_file_path` instead of
+    // memory, enabling the compact mode; the file is truncated. Lookups are slower but the memory
+    // stays flat.
+    #[cfg(feature = "fs")]
+    pub fn use_disk_transaction_index(
+        &mut self,
+        transaction_index_file_path: String,
+    ) -> Result<()> {
+        self.compact = true;
+        self.transaction_index = CompactIndex::on_disk(transaction_index_file_path)?;
         Ok(())
     }
 
-    pub(crate) fn export_accounts_to_file(&self, output_file_path: String) -> Result<()> {
-        let mut wtr = csv::Writer::from_path(output_file_path)?;
-        for (_, _account) in self.accounts.iter() {
-            wtr.serialize(_account)?;
+    // transactions without a timestamp are out of any range.
+    pub(crate) fn in_date_range(&self, transaction: &Transaction) -> bool {
+        if self.from_timestamp.is_none() && self.to_timestamp.is_none() {
+            return true;
+        }
+        match transaction.timestamp {
+            Some(timestamp) => {
+                self.from_timestamp.is_none_or(|from| timestamp >= from)
+                    && self.to_timestamp.is_none_or(|to| timestamp <= to)
+            }
+            None => false,
+        }
+    }
+
+    // record a failed record in `failed_transactions` and let the observers know about it.
+    pub(crate) fn record_failure(&mut self, record: &StringRecord, e: anyhow::Error) {
+        for observer in self.observers.iter() {
+            observer.on_transaction_failed(record, &e);
+        }
+        self.count_failure(record);
+        let mut failed_tx = FailedTransaction::new(record, &e);
+        if self.failure_context {
+            if let Some(byte) = failed_tx.byte {
+                // after a CRLF, the reader positions the record on the LF.
+                if let Some((raw, skipped)) = self.raw_input_line(byte) {
+                    if skipped {
+                        failed_tx.byte = Some(byte + 1);
+                        failed_tx.line = failed_tx.line.map(|line| line + 1);
+                    }
+                    failed_tx.raw = Some(raw);
+                }
+            }
+        }
+        #[cfg(feature = "fs")]
+        if let Some(stream) = &self.failure_stream {
+            let mut wtr = stream
+                .writer
+                .lock()
+                .expect("the failure stream is poisoned");
+            if let Err(e) = self.serialize_failure(&mut wtr, &failed_tx) {
+                eprintln!("Could not write the failure: {}", e);
+            }
+            if self.failed_transactions.len() >= stream.sample_size {
+                self.dropped_failures += 1;
+                return;
+            }
+        }
+        self.failed_transactions.push(failed_tx);
+    }
+
+    // the number of failures, the streamed ones included.
+    pub fn failure_count(&self) -> usize {
+        self.failed_transactions.len() + self.dropped_failures
+    }
+
+    // write the failures recorded from now on to `failures_file_path`, where
+    // `export_failed_txs_to_file` would write them, keeping only the first `sample_size` in
+    // memory.
+    #[cfg(feature = "fs")]
+    pub fn stream_failures_to_file(
+        &mut self,
+        failures_file_path: String,
+        sample_size: usize,
+    ) -> Result<()> {
+        let writer = self
+            .csv_writer_options
+            .writer_builder()
+            .from_path(failures_file_path)?;
+        self.failure_stream = Some(FailureStream {
+            writer: Arc::new(Mutex::new(writer)),
+            sample_size,
+        });
+        Ok(())
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn flush_failure_stream(&self) -> Result<()> {
+        if let Some(stream) = &self.failure_stream {
+            stream
+                .writer
+                .lock()
+                .expect("the failure stream is poisoned")
+                .flush()?;
+        }
+        Ok(())
+    }
+
+    // the line of the input file starting at `byte`, without its line ending, and whether a LF
+    // had to be skipped to reach it.
+    #[cfg(feature = "fs")]
+    fn raw_input_line(&self, byte: u64) -> Option<(String, bool)> {
+        let mut reader = BufReader::new(File::open(&self.input_file_path).ok()?);
+        reader.seek(SeekFrom::Start(byte)).ok()?;
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let skipped = line == "\n";
+        if skipped {
+            line.clear();
+            reader.read_line(&mut line).ok()?;
+        }
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        Some((line.strip_suffix('\r').unwrap_or(line).to_string(), skipped))
+    }
+
+    // without a file system, there is no input file to read the line from.
+    #[cfg(not(feature = "fs"))]
+    fn raw_input_line(&self, _byte: u64) -> Option<(String, bool)> {
+        None
+    }
+
+    pub fn register_observer(&mut self, observer: Arc<dyn EngineObserver>) {
+        if self.string_clients {
+            for (client, label) in self.client_labels.iter().enumerate() {
+                observer.on_client_labeled(client as u32, label);
+            }
+        }
+        self.observers.push(observer);
+    }
+
+    // handle the transactions whose type is `r#type` (case insensitive, like the built-in types)
+    // with `handler` instead of rejecting them as unknown.
+    pub fn register_handler(&mut self, r#type: &str, handler: Arc<dyn TransactionHandler>) {
+        self.handlers.insert(r#type.to_lowercase(), handler);
+    }
+
+    // merge several account exports into the accounts map; balances of clients found in more than
+    // one export are summed. Returns the clients whose locked flag differs between exports, those
+    // are kept locked.
+    #[cfg(feature = "fs")]
+    pub fn merge_account_exports(&mut self, input_file_paths: &[String]) -> Result<Vec<u32>> {
+        let mut conflicts = Vec::new();
+        for input_file_path in input_file_paths.iter() {
+            let mut csv_reader = csv::ReaderBuilder::new()
+                .has_headers(true)
+                .trim(csv::Trim::All)
+                .from_path(input_file_path)?;
+            for record in csv_reader.deserialize::<Account>() {
+                let exported_account = record?;
+                match self.accounts.get_mut(&exported_account.client) {
+                    Some(account) => {
+                        if account.locked != exported_account.locked
+                            && !conflicts.contains(&account.client)
+                        {
+                            conflicts.push(account.client);
+                        }
+                        account.available += exported_account.available;
+                        account.held += exported_account.held;
+                        account.total += exported_account.total;
+                        account.locked |= exported_account.locked;
+                    }
+                    None => {
+                        self.accounts
+                            .insert(exported_account.client, exported_account);
+                    }
+                }
+            }
+        }
+        Ok(conflicts)
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn export_accounts_to_file(&self, output_file_path: String) -> Result<()> {
+        self.write_accounts(File::create(output_file_path)?)
+    }
+
+    pub(crate) fn write_accounts<W: Write>(&self, writer: W) -> Result<()> {
+        let mut wtr = self.csv_writer_options.writer_builder().from_writer(writer);
+        if let Some(columns) = &self.account_columns {
+            return self.write_account_columns(wtr, columns);
+        }
+        for _account in self.sorted_accounts() {
+            let metadata = self.clients.get(&_account.client);
+            let metadata_field = |field: fn(&ClientMetadata) -> &str| {
+                if self.clients.is_empty() {
+                    None
+                } else {
+                    Some(metadata.map(field).unwrap_or_default())
+                }
+            };
+            wtr.serialize(AccountRow {
+                client: self.client_label(_account.client),
+                available: _account.available,
+                held: _account.held,
+                total: _account.total,
+                locked: _account.locked,
+                name: metadata_field(|m| &m.name),
+                tier: metadata_field(|m| &m.tier),
+                country: metadata_field(|m| &m.country),
+            })?;
         }
         wtr.flush()?;
         Ok(())
     }
 
-    pub(crate) fn export_failed_txs_to_file(
+    fn write_account_columns<W: Write>(
         &self,
-        failed_txs_output_file_path: String,
+        mut wtr: csv::Writer<W>,
+        columns: &[AccountColumn],
     ) -> Result<()> {
-        let mut wtr = csv::Writer::from_path(failed_txs_output_file_path)?;
+        let stats = AccountStats::default();
+        let open_disputes = if columns.contains(&AccountColumn::OpenDisputes) {
+            self.open_disputes()?
+        } else {
+            BTreeMap::new()
+        };
+        let mut header_written = false;
+        for account in self.sorted_accounts() {
+            // like the default layout, an export without accounts is empty.
+            if !header_written {
+                wtr.write_record(columns.iter().map(AccountColumn::name))?;
+                header_written = true;
+            }
+            let metadata = self.clients.get(&account.client);
+            let metadata_field = |field: fn(&ClientMetadata) -> &str| {
+                Cell::Text(metadata.map(field).unwrap_or_default())
+            };
+            let row = columns
+                .iter()
+                .map(|column| match column {
+                    AccountColumn::Client => Cell::Label(self.client_label(account.client)),
+                    AccountColumn::Available => Cell::Amount(account.available),
+                    AccountColumn::Held => Cell::Amount(account.held),
+                    AccountColumn::Total => Cell::Amount(account.total),
+                    AccountColumn::Locked => Cell::Flag(account.locked),
+                    AccountColumn::Name => metadata_field(|m| &m.name),
+                    AccountColumn::Tier => metadata_field(|m| &m.tier),
+                    AccountColumn::Country => metadata_field(|m| &m.country),
+                    AccountColumn::Class => {
+                        Cell::Label(format!("{:?}", account.class).to_lowercase())
+                    }
+                    AccountColumn::OpenDisputes => Cell::Count(
+                        open_disputes
+                            .get(&account.client)
+                            .copied()
+                            .unwrap_or_default(),
+                    ),
+                    AccountColumn::RiskScore => Cell::Label(format!(
+                        "{:.2}",
+                        self.account_stats
+                            .get(&account.client)
+                            .unwrap_or(&stats)
+                            .risk_score()
+                    )),
+                })
+                .collect::<Vec<Cell>>();
+            wtr.serialize(row)?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    // (client, number of disputed transactions not resolved or charged back yet)
+    pub fn open_disputes(&self) -> Result<BTreeMap<u32, usize>> {
+        if self.compact {
+            return self.transaction_index.open_disputes();
+        }
+        Ok(self
+            .accounts
+            .values()
+            .map(|account| {
+                let disputed = account.transactions.values().filter(|tx| tx.disputed);
+                (account.client, disputed.count())
+            })
+            .filter(|(_, open_disputes)| *open_disputes > 0)
+            .collect())
+    }
+
+    // the accounts ordered by client, by their opaque client for string clients.
+    fn sorted_accounts(&self) -> Box<dyn Iterator<Item = &Account> + '_> {
+        if self.string_clients {
+            Box::new(
+                self.client_ids
+                    .values()
+                    .filter_map(|client| self.accounts.get(client)),
+            )
+        } else {
+            Box::new(self.accounts.values())
+        }
+    }
+
+    // the client as written in the input.
+    pub fn client_label(&self, client: u32) -> String {
+        match self.client_labels.get(client as usize) {
+            Some(label) if self.string_clients => label.clone(),
+            _ => client.to_string(),
+        }
+    }
+
+    // the client number of a client as written in the input.
+    pub fn find_client(&self, label: &str) -> Option<u32> {
+        if self.string_clients {
+            self.client_ids.get(label).copied()
+        } else {
+            label.parse().ok()
+        }
+    }
+
+    // deserialize a transaction record; string clients are given their client number first and
+    // the amount is normalized as `amount_parsing` asks.
+    pub(crate) fn deserialize_record(&mut self, record: &StringRecord) -> Result<Transaction> {
+        let client = self.string_client(record);
+        let raw_amount = record.get(AMOUNT_FIELD).unwrap_or_default();
+        let amount = self.amount_parsing.normalize(raw_amount)?;
+        if let Some(amount) = &amount {
+            self.warn_normalized_amount(record, amount);
+        }
+        deserialize_fields(record, client.as_deref(), amount.as_deref())
+    }
+
+    pub(crate) fn warn_normalized_amount(&mut self, record: &StringRecord, amount: &str) {
+        if self.warn_normalized_amounts {
+            let raw_amount = record.get(AMOUNT_FIELD).unwrap_or_default();
+            let message = format!("Amount {:?} read as {}.", raw_amount, amount);
+            self.record_warning(Severity::Info, Some(record), message);
+        }
+    }
+
+    // the client number standing for the string client of the record, given in order of
+    // appearance.
+    fn string_client(&mut self, record: &StringRecord) -> Option<String> {
+        if !self.string_clients {
+            return None;
+        }
+        let label = record.get(1).unwrap_or_default();
+        if label.is_empty() {
+            return None;
+        }
+        let client = match self.client_ids.get(label) {
+            Some(client) => *client,
+            None => {
+                let client = self.client_labels.len() as u32;
+                self.client_ids.insert(label.to_string(), client);
+                self.client_labels.push(label.to_string());
+                for observer in self.observers.iter() {
+                    observer.on_client_labeled(client, label);
+                }
+                client
+            }
+        };
+        Some(client.to_string())
+    }
+
+    // the accounts as a JSON array, with the same fields and precision as the CSV export.
+    pub fn export_accounts_to_json(&self) -> String {
+        let accounts = self
+            .sorted_accounts()
+            .map(|account| {
+                let client = if self.string_clients {
+                    let label = self.client_label(account.client);
+                    format!("\"{}\"", label.replace('"', "\\\""))
+                } else {
+                    account.client.to_string()
+                };
+                format!(
+                    "{{\"client\":{},\"available\":{:.4},\"held\":{:.4},\"total\":{:.4},\"locked\":{}}}",
+                    client, account.available, account.held, account.total, account.locked
+                )
+            })
+            .collect::<Vec<String>>();
+        format!("[{}]", accounts.join(","))
+    }
+
+    // the accounts, failed transactions, warnings and flags exports, in memory.
+    pub fn exports_to_strings(&self) -> Result<Exports> {
+        let mut exports: [Vec<u8>; 4] = Default::default();
+        let [accounts, failed_txs, warnings, flags] = &mut exports;
+        self.write_accounts(accounts)?;
+        self.write_failed_txs(failed_txs)?;
+        self.write_warnings(warnings)?;
+        self.write_flags(flags)?;
+        let [accounts, failed_transactions, warnings, flags] = exports.map(String::from_utf8);
+        Ok(Exports {
+            accounts: accounts?,
+            failed_transactions: failed_transactions?,
+            warnings: warnings?,
+            flags: flags?,
+        })
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn export_failed_txs_to_file(&self, failed_txs_output_file_path: String) -> Result<()> {
+        self.write_failed_txs(File::create(failed_txs_output_file_path)?)
+    }
+
+    pub(crate) fn write_failed_txs<W: Write>(&self, writer: W) -> Result<()> {
+        let mut wtr = self.csv_writer_options.writer_builder().from_writer(writer);
         for failed_tx in self.failed_transactions.iter() {
-            wtr.serialize(failed_tx)?;
+            self.serialize_failure(&mut wtr, failed_tx)?;
         }
         wtr.flush()?;
         Ok(())
     }
 
-    fn formatted_bad_record(record: &StringRecord, e: Box<dyn Error>) -> String {
-        let bad_record = record.iter().collect::<Vec<&str>>();
-        let formatted_bad_record = bad_record.join(",");
-        format!("{},{}", formatted_bad_record, e)
+    fn serialize_failure<W: Write>(
+        &self,
+        wtr: &mut csv::Writer<W>,
+        failed_tx: &FailedTransaction,
+    ) -> Result<()> {
+        if self.failure_context {
+            wtr.serialize(failed_tx.with_context(&self.failure_mask))?;
+        } else {
+            wtr.serialize(failed_tx.with_category(&self.failure_mask))?;
+        }
+        Ok(())
+    }
+}
+
+// deserialize a transaction record with its client and amount fields replaced, if given.
+pub(crate) fn deserialize_fields(
+    record: &StringRecord,
+    client: Option<&str>,
+    amount: Option<&str>,
+) -> Result<Transaction> {
+    if client.is_none() && amount.is_none() {
+        if let Some(transaction) = fast_parse::parse_transaction(record) {
+            return Ok(transaction);
+        }
+        return Ok(record.deserialize(None)?);
+    }
+    let mut fields = record.iter().collect::<Vec<&str>>();
+    if let Some(client) = client {
+        fields[1] = client;
+    }
+    if let Some(amount) = amount {
+        fields[AMOUNT_FIELD] = amount;
     }
+    Ok(StringRecord::from(fields).deserialize(None)?)
 }
 
 impl<'de> Deserialize<'de> for TransactionType {
@@ -165,6 +996,9 @@ impl<'de> Deserialize<'de> for TransactionType {
             "dispute" => TransactionType::Dispute,
             "resolve" => TransactionType::Resolve,
             "chargeback" => TransactionType::ChargeBack,
+            "authorize" => TransactionType::Authorize,
+            "capture" => TransactionType::Capture,
+            "representment" => TransactionType::Representment,
             _ => TransactionType::Unknown(s),
         };
         Ok(tx_type)
@@ -0,0 +1,116 @@
+use crate::account::Account;
+use crate::amount::AMOUNT_FIELD;
+use crate::payment_engine::{PaymentEngine, Transaction, TransactionType};
+#[cfg(feature = "fs")]
+use anyhow::Result;
+use csv::StringRecord;
+#[cfg(feature = "fs")]
+use std::fs::File;
+
+// The rounding the total of an account went through, in currency units, to quantify the drift
+// of the f32 arithmetic from the decimal amounts of the input.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rounding {
+    // the amounts as read, minus the decimal amounts of the input.
+    pub input: f64,
+    // the changes of the total as computed, minus the amounts as read.
+    pub arithmetic: f64,
+}
+
+impl Rounding {
+    pub fn total(&self) -> f64 {
+        self.input + self.arithmetic
+    }
+}
+
+// the change of the account's total the processed transaction asks for, in the amounts as read.
+// Charge backs and representments use the stored amount of the original transaction, so the rounding of reading it
+// is only counted for the deposit or withdrawal itself; custom transactions are not audited.
+pub(crate) fn requested_change(transaction: &Transaction, account: &Account) -> Option<f64> {
+    match transaction.r#type {
+        TransactionType::Deposit => Some(transaction.amount as f64),
+        TransactionType::Withdrawal => Some(-(transaction.amount as f64)),
+        TransactionType::ChargeBack => account
+            .transactions
+            .get(&transaction.tx)
+            .map(|original| -(original.amount as f64)),
+        TransactionType::Capture => Some(-(transaction.amount as f64)),
+        TransactionType::Representment => account
+            .transactions
+            .get(&transaction.tx)
+            .map(|original| original.amount as f64),
+        _ => None,
+    }
+}
+
+impl PaymentEngine {
+    // add the rounding of a processed transaction, which changed the total from `total_before`
+    // where `requested` was asked for.
+    pub(crate) fn audit_rounding(
+        &mut self,
+        record: &StringRecord,
+        transaction: &Transaction,
+        total_before: f32,
+        requested: f64,
+    ) {
+        let total = match self.accounts.get(&transaction.client) {
+            Some(account) => account.total,
+            None => return,
+        };
+        // the decimal amount, normalized as it was for reading it.
+        let raw_amount = record.get(AMOUNT_FIELD).unwrap_or_default();
+        let decimal = match self.amount_parsing.normalize(raw_amount) {
+            Ok(Some(amount)) => amount.parse::<f64>().ok(),
+            Ok(None) => raw_amount.parse::<f64>().ok(),
+            Err(_) => None,
+        };
+        let decimal = match (&transaction.r#type, decimal) {
+            (TransactionType::Deposit, Some(decimal)) => decimal,
+            (TransactionType::Withdrawal, Some(decimal)) => -decimal,
+            _ => requested,
+        };
+        let rounding = self.rounding.entry(transaction.client).or_default();
+        rounding.input += requested - decimal;
+        rounding.arithmetic += (total as f64 - total_before as f64) - requested;
+    }
+
+    // the accounts whose total rounding is larger than the precision audit threshold, sorted by
+    // client.
+    pub fn rounding_over_threshold(&self) -> Vec<(u32, Rounding)> {
+        let threshold = match self.precision_audit {
+            Some(threshold) => threshold,
+            None => return Vec::new(),
+        };
+        self.rounding
+            .iter()
+            .filter(|(_, rounding)| rounding.total().abs() > threshold)
+            .map(|(client, rounding)| (*client, *rounding))
+            .collect()
+    }
+
+    // write the accounts over the precision audit threshold as
+    // `client,input_rounding,arithmetic_rounding,total_rounding`.
+    #[cfg(feature = "fs")]
+    pub fn export_rounding_to_file(&self, output_file_path: String) -> Result<()> {
+        let mut wtr = self
+            .csv_writer_options
+            .writer_builder()
+            .from_writer(File::create(output_file_path)?);
+        wtr.write_record([
+            "client",
+            "input_rounding",
+            "arithmetic_rounding",
+            "total_rounding",
+        ])?;
+        for (client, rounding) in self.rounding_over_threshold() {
+            wtr.write_record([
+                self.client_label(client),
+                format!("{:.8}", rounding.input),
+                format!("{:.8}", rounding.arithmetic),
+                format!("{:.8}", rounding.total()),
+            ])?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+use crate::account::BALANCE_TOLERANCE;
+use crate::payment_engine::{PaymentEngine, Transaction, TransactionType};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Deserialize)]
+struct StatementEntry {
+    client: u32,
+    tx: u64,
+    // signed; credits are positive and debits are negative.
+    amount: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakKind {
+    MissingInStatement,
+    MissingInEngine,
+    AmountMismatch,
+    UnexplainedBalance,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Break {
+    pub client: u32,
+    pub tx: Option<u64>,
+    pub kind: BreakKind,
+    pub engine_amount: Option<f32>,
+    pub statement_amount: Option<f32>,
+}
+
+// what a transaction of the history moved in the total of its account, with everything applied
+// under its ID: the capture of an authorization, the fee charged with it and a charge back still
+// standing. Disputes and resolves only move funds between available and held.
+fn ledger_movement(transaction: &Transaction) -> f32 {
+    let movement = match transaction.r#type {
+        TransactionType::Deposit => transaction.amount,
+        TransactionType::Withdrawal => -transaction.amount,
+        TransactionType::Authorize => -transaction.captured_amount,
+        _ => 0.0,
+    };
+    let charged_back = if transaction.charged_back {
+        transaction.amount
+    } else {
+        0.0
+    };
+    movement - transaction.fee - charged_back
+}
+
+impl PaymentEngine {
+    // compare the processed accounts against a settlement statement and list every break, per client.
+    pub fn reconcile(&self, statement_file_path: String) -> Result<Vec<Break>> {
+        // the histories are in the compact index, and all of its transactions would be missing.
+        if self.compact {
+            return Err(anyhow!(
+                "Can't reconcile in the compact mode; it doesn't keep the histories of the accounts."
+            ));
+        }
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .trim(csv::Trim::All)
+            .from_path(statement_file_path)?;
+        // (client, (transaction_id, amount))
+        let mut statement: BTreeMap<u32, BTreeMap<u64, f32>> = BTreeMap::new();
+        for entry in csv_reader.deserialize::<StatementEntry>() {
+            let entry = entry?;
+            *statement
+                .entry(entry.client)
+                .or_default()
+                .entry(entry.tx)
+                .or_default() += entry.amount;
+        }
+
+        let mut breaks = Vec::new();
+        let empty = BTreeMap::new();
+        for (client, account) in self.accounts.iter() {
+            let statement_txs = statement.get(client).unwrap_or(&empty);
+            // (transaction_id, movement) of the history; a bank doesn't post an authorization
+            // until it is captured.
+            let engine_txs: BTreeMap<u64, f32> = account
+                .transactions
+                .iter()
+                .filter(|(_, transaction)| {
+                    transaction.r#type != TransactionType::Authorize
+                        || transaction.captured_amount > 0.0
+                })
+                .map(|(tx_id, transaction)| (*tx_id, ledger_movement(transaction)))
+                .collect();
+            for (tx_id, engine_amount) in engine_txs.iter() {
+                match statement_txs.get(tx_id) {
+                    Some(statement_amount) => {
+                        if (engine_amount - statement_amount).abs() >= BALANCE_TOLERANCE {
+                            breaks.push(Break {
+                                client: *client,
+                                tx: Some(*tx_id),
+                                kind: BreakKind::AmountMismatch,
+                                engine_amount: Some(*engine_amount),
+                                statement_amount: Some(*statement_amount),
+                            });
+                        }
+                    }
+                    None => breaks.push(Break {
+                        client: *client,
+                        tx: Some(*tx_id),
+                        kind: BreakKind::MissingInStatement,
+                        engine_amount: Some(*engine_amount),
+                        statement_amount: None,
+                    }),
+                }
+            }
+            for (tx_id, statement_amount) in statement_txs.iter() {
+                if !engine_txs.contains_key(tx_id) {
+                    breaks.push(Break {
+                        client: *client,
+                        tx: Some(*tx_id),
+                        kind: BreakKind::MissingInEngine,
+                        engine_amount: None,
+                        statement_amount: Some(*statement_amount),
+                    });
+                }
+            }
+            let statement_balance: f32 = statement_txs.values().sum();
+            if (account.total - statement_balance).abs() >= BALANCE_TOLERANCE {
+                breaks.push(Break {
+                    client: *client,
+                    tx: None,
+                    kind: BreakKind::UnexplainedBalance,
+                    engine_amount: Some(account.total),
+                    statement_amount: Some(statement_balance),
+                });
+            }
+        }
+
+        // clients the engine has never seen.
+        for (client, statement_txs) in statement.iter() {
+            if self.accounts.contains_key(client) {
+                continue;
+            }
+            for (tx_id, statement_amount) in statement_txs.iter() {
+                breaks.push(Break {
+                    client: *client,
+                    tx: Some(*tx_id),
+                    kind: BreakKind::MissingInEngine,
+                    engine_amount: None,
+                    statement_amount: Some(*statement_amount),
+                });
+            }
+            breaks.push(Break {
+                client: *client,
+                tx: None,
+                kind: BreakKind::UnexplainedBalance,
+                engine_amount: None,
+                statement_amount: Some(statement_txs.values().sum()),
+            });
+        }
+        breaks.sort_by_key(|b| b.client);
+        Ok(breaks)
+    }
+
+    pub fn export_breaks_to_file(breaks: &[Break], output_file_path: String) -> Result<()> {
+        let mut wtr = csv::Writer::from_path(output_file_path)?;
+        for _break in breaks.iter() {
+            wtr.serialize(_break)?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fee::{FeeRule, FeeSchedule};
+
+    #[test]
+    fn compact_mode_is_rejected() {
+        let path = std::env::temp_dir().join(format!("reconcile-{}.csv", std::process::id()));
+        std::fs::write(&path, "client,tx,amount\n1,1,1.0\n").unwrap();
+        let statement = path.to_string_lossy().into_owned();
+        let mut engine = PaymentEngine::default();
+        engine.parse_transactions_from_str("type,client,tx,amount\ndeposit,1,1,1.0\n");
+        assert!(engine.reconcile(statement.clone()).unwrap().is_empty());
+
+        let mut compact = PaymentEngine {
+            compact: true,
+            ..Default::default()
+        };
+        compact.parse_transactions_from_str("type,client,tx,amount\ndeposit,1,1,1.0\n");
+        let result = compact.reconcile(statement);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn authorizations_captures_and_fees() {
+        let mut engine = PaymentEngine {
+            fee_schedule: Some(FeeSchedule {
+                rules: vec![FeeRule {
+                    r#type: TransactionType::Deposit,
+                    class: None,
+                    min_amount: 0.0,
+                    percent: 0.0,
+                    fixed: 1.0,
+                }],
+            }),
+            ..Default::default()
+        };
+        engine.parse_transactions_from_str(
+            "type,client,tx,amount
+deposit,1,1,100.0
+authorize,1,2,30.0
+capture,1,2,20.0
+authorize,1,3,10.0
+withdrawal,1,4,10.0
+",
+        );
+        assert!(engine.failed_transactions.is_empty());
+        let path = std::env::temp_dir().join(format!("reconcile-fees-{}.csv", std::process::id()));
+        let statement = path.to_string_lossy().into_owned();
+        // the fee of the deposit is posted under its ID, and the open authorization not at all.
+        std::fs::write(
+            &path,
+            "client,tx,amount\n1,1,100.0\n1,1,-1.0\n1,2,-20.0\n1,4,-10.0\n",
+        )
+        .unwrap();
+        let breaks = engine.reconcile(statement.clone()).unwrap();
+        assert!(breaks.is_empty(), "{:?}", breaks);
+
+        std::fs::write(&path, "client,tx,amount\n1,1,100.0\n1,4,-10.0\n").unwrap();
+        let breaks = engine.reconcile(statement).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let breaks: Vec<_> = breaks
+            .iter()
+            .map(|b| (b.tx, b.kind.clone(), b.engine_amount, b.statement_amount))
+            .collect();
+        assert_eq!(
+            breaks,
+            [
+                (Some(1), BreakKind::AmountMismatch, Some(99.0), Some(100.0)),
+                (Some(2), BreakKind::MissingInStatement, Some(-20.0), None),
+                (None, BreakKind::UnexplainedBalance, Some(69.0), Some(90.0)),
+            ]
+        );
+    }
+}
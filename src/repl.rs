@@ -0,0 +1,133 @@
+use crate::payment_engine::{PaymentEngine, Transaction};
+use anyhow::Result;
+use csv::StringRecord;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "commands:
+  account <client>                      show the balances of a client
+  tx <tx>                               show a stored transaction
+  disputes open                         list the transactions under dispute
+  apply <type> <client> <tx> [amount]   apply a transaction
+  forget <client>                       drop the history and personal data of a client
+  help                                  show this message
+  quit                                  leave the repl";
+
+// read queries from stdin until it is closed or `quit` is typed, answering each one against the
+// engine state.
+pub fn run(engine: &mut PaymentEngine) -> Result<()> {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    print!("> ");
+    stdout.flush()?;
+    for line in stdin.lock().lines() {
+        let line = line?;
+        let words = line.split_whitespace().collect::<Vec<&str>>();
+        match words.as_slice() {
+            [] => {}
+            ["quit"] | ["exit"] => break,
+            ["help"] => println!("{}", HELP),
+            ["account", client] => match engine.find_client(client) {
+                Some(client) => show_account(engine, client),
+                None => println!("client {} not found", client),
+            },
+            ["tx", tx_id] => match tx_id.parse::<u64>() {
+                Ok(tx_id) => show_transaction(engine, tx_id),
+                Err(e) => println!("invalid tx {}: {}", tx_id, e),
+            },
+            ["disputes", "open"] => show_open_disputes(engine),
+            ["forget", client] => match engine.find_client(client) {
+                Some(client_id) => match engine.forget_client(client_id) {
+                    Ok(_) => println!("client {} forgotten", client),
+                    Err(e) => println!("{}", e),
+                },
+                None => println!("client {} not found", client),
+            },
+            ["apply", fields @ ..] if fields.len() >= 3 => apply(engine, fields),
+            _ => println!("unknown command, type `help` for the list of commands"),
+        }
+        print!("> ");
+        stdout.flush()?;
+    }
+    println!();
+    Ok(())
+}
+
+fn show_account(engine: &PaymentEngine, client: u32) {
+    match engine.accounts.get(&client) {
+        Some(account) => println!(
+            "client {}: available {:.4}, held {:.4}, total {:.4}, locked {}",
+            engine.client_label(account.client),
+            account.available,
+            account.held,
+            account.total,
+            account.locked
+        ),
+        None => println!("client {} not found", engine.client_label(client)),
+    }
+    if let Some(metadata) = engine.clients.get(&client) {
+        println!(
+            "  {} ({}, {})",
+            metadata.name, metadata.tier, metadata.country
+        );
+    }
+}
+
+fn show_transaction(engine: &PaymentEngine, tx_id: u64) {
+    let found = engine
+        .accounts
+        .values()
+        .find_map(|account| account.transactions.get(&tx_id));
+    match found {
+        Some(transaction) => print_transaction(engine, transaction),
+        None => println!("tx {} not found", tx_id),
+    }
+}
+
+fn show_open_disputes(engine: &PaymentEngine) {
+    let mut count = 0;
+    for account in engine.accounts.values() {
+        for transaction in account.transactions.values().filter(|tx| tx.disputed) {
+            print_transaction(engine, transaction);
+            count += 1;
+        }
+    }
+    println!("{} open disputes", count);
+}
+
+fn apply(engine: &mut PaymentEngine, fields: &[&str]) {
+    let record = StringRecord::from(fields.to_vec());
+    let failed_before = engine.failed_transactions.len();
+    match engine.deserialize_record(&record) {
+        Ok(transaction) => {
+            engine.apply_transaction(&record, &transaction);
+            if engine.failed_transactions.len() == failed_before {
+                println!("applied");
+                show_account(engine, transaction.client);
+            }
+        }
+        Err(e) => engine.record_failure(&record, e),
+    }
+    for failed_tx in engine.failed_transactions[failed_before..].iter() {
+        println!("failed: {}", failed_tx);
+    }
+}
+
+fn print_transaction(engine: &PaymentEngine, transaction: &Transaction) {
+    println!(
+        "tx {}: {:?} of {:.4} for client {}{}{}",
+        transaction.tx,
+        transaction.r#type,
+        transaction.amount,
+        engine.client_label(transaction.client),
+        if transaction.disputed {
+            ", disputed"
+        } else {
+            ""
+        },
+        if transaction.charged_back {
+            ", charged back"
+        } else {
+            ""
+        }
+    );
+}
@@ -0,0 +1,77 @@
+use crate::payment_engine::PaymentEngine;
+use anyhow::{anyhow, Result};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+// Where `parse_transactions_from` starts reading the transactions file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StartPosition {
+    // byte offset of the start of a line, such as the end offset of an earlier run.
+    Offset(u64),
+    // line number, the header being line 1.
+    Line(u64),
+}
+
+impl PaymentEngine {
+    // same as `parse_transactions` but skips the records before `start`, to continue an
+    // interrupted run or process a file in parts. The balances of the records before it are
+    // expected to be loaded already, e.g. with `merge_account_exports`.
+    pub fn parse_transactions_from(&mut self, start: StartPosition) -> Result<()> {
+        let position = self.locate(start)?;
+        let mut csv_reader = self.new_untrimmed_file_reader()?;
+        // reads the header first, then continues from the position.
+        csv_reader.seek(position)?;
+        self.parse_records(&mut csv_reader);
+        Ok(())
+    }
+
+    // the position of the start of the line `start` points to.
+    fn locate(&self, start: StartPosition) -> Result<csv::Position> {
+        let mut reader = BufReader::new(File::open(self.input_file_path())?);
+        let mut byte = 0;
+        let mut line = 1;
+        // a position at the start of the file or right after a line ending.
+        let mut at_line_start = true;
+        loop {
+            let reached = match start {
+                StartPosition::Offset(offset) => byte >= offset,
+                StartPosition::Line(target) => line >= target,
+            };
+            if reached {
+                if let StartPosition::Offset(offset) = start {
+                    if byte > offset || !at_line_start {
+                        return Err(anyhow!("offset {} is not at the start of a line", offset));
+                    }
+                }
+                break;
+            }
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                return Err(anyhow!("the start position is past the end of the file"));
+            }
+            let mut consumed = 0;
+            for b in buffer.iter() {
+                if let StartPosition::Offset(offset) = start {
+                    if byte >= offset {
+                        break;
+                    }
+                }
+                if let StartPosition::Line(target) = start {
+                    if line >= target {
+                        break;
+                    }
+                }
+                byte += 1;
+                consumed += 1;
+                at_line_start = *b == b'\n' || *b == b'\r';
+                if *b == b'\n' {
+                    line += 1;
+                }
+            }
+            reader.consume(consumed);
+        }
+        let mut position = csv::Position::new();
+        position.set_byte(byte).set_line(line).set_record(line - 1);
+        Ok(position)
+    }
+}
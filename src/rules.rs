@@ -0,0 +1,396 @@
+use crate::output::AccountColumn;
+use crate::payment_engine::{PaymentEngine, Transaction, TransactionType};
+use crate::warning::Severity;
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use serde::Serialize;
+#[cfg(feature = "fs")]
+use std::fs::File;
+use std::io::Write;
+use std::str::FromStr;
+
+// What a rule does to the account that trips it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    // lock the account, and flag it.
+    #[default]
+    Lock,
+    // only flag the account for review.
+    Flag,
+}
+
+impl FromStr for RuleAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "lock" => Ok(RuleAction::Lock),
+            "flag" => Ok(RuleAction::Flag),
+            _ => Err(anyhow!("unknown rule action {}, expected lock or flag", s)),
+        }
+    }
+}
+
+// The abuse control tripped by the accounts with too many charge backs over the run: more than
+// `max_chargebacks` of them, or charge backs worth more than `max_chargeback_ratio` of their
+// deposits. Represented charge backs still count, so the rule locks the account again once a
+// representment has unlocked it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ChargebackRule {
+    pub max_chargebacks: Option<usize>,
+    pub max_chargeback_ratio: Option<f64>,
+    pub action: RuleAction,
+}
+
+// Flags the accounts whose risk score goes over `max_score`, once they have attempted at least
+// `min_transactions` transactions so a single early failure or dispute doesn't trip it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct RiskRule {
+    pub max_score: f64,
+    pub min_transactions: usize,
+}
+
+// transactions a day scoring the full velocity.
+const VELOCITY_LIMIT: f64 = 100.0;
+
+// What the rules know of an account, over the run.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AccountStats {
+    pub deposited: f64,
+    pub chargebacks: usize,
+    pub charged_back: f64,
+    pub deposits: usize,
+    pub disputes: usize,
+    // transactions applied, and failures recorded, for the account.
+    pub transactions: usize,
+    pub failures: usize,
+    // day of the latest timestamp, the transactions applied on it, and the most applied on a day.
+    pub day: u64,
+    pub day_transactions: usize,
+    pub peak_day_transactions: usize,
+    // running mean and sum of squared deviations (Welford) of the total balance after each
+    // transaction.
+    pub balance_mean: f64,
+    pub balance_m2: f64,
+}
+
+impl AccountStats {
+    // count a transaction applied to the account, leaving `total` in it.
+    fn record(&mut self, transaction: &Transaction, original_amount: Option<f32>, total: f32) {
+        match transaction.r#type {
+            TransactionType::Deposit => {
+                self.deposits += 1;
+                self.deposited += transaction.amount as f64;
+            }
+            TransactionType::Dispute => self.disputes += 1,
+            TransactionType::ChargeBack => {
+                self.chargebacks += 1;
+                self.charged_back += original_amount.unwrap_or_default() as f64;
+            }
+            _ => {}
+        }
+        self.transactions += 1;
+        if let Some(timestamp) = transaction.timestamp {
+            let day = timestamp / 86_400;
+            if day != self.day {
+                self.day = day;
+                self.day_transactions = 0;
+            }
+            self.day_transactions += 1;
+            self.peak_day_transactions = self.peak_day_transactions.max(self.day_transactions);
+        }
+        let delta = total as f64 - self.balance_mean;
+        self.balance_mean += delta / self.transactions as f64;
+        self.balance_m2 += delta * (total as f64 - self.balance_mean);
+    }
+
+    // a score from 0 to 100, the mean of four parts from 0 to 1: the disputes per deposit, the
+    // failures per attempted transaction, the most transactions applied on a day against
+    // `VELOCITY_LIMIT`, and the coefficient of variation of the total balance.
+    pub fn risk_score(&self) -> f64 {
+        let attempted = self.transactions + self.failures;
+        if attempted == 0 {
+            return 0.0;
+        }
+        let dispute_frequency = if self.deposits == 0 {
+            (self.disputes > 0) as u8 as f64
+        } else {
+            self.disputes as f64 / self.deposits as f64
+        };
+        let failure_rate = self.failures as f64 / attempted as f64;
+        let velocity = self.peak_day_transactions as f64 / VELOCITY_LIMIT;
+        let deviation = if self.transactions == 0 {
+            0.0
+        } else {
+            (self.balance_m2 / self.transactions as f64).sqrt()
+        };
+        let volatility = if self.balance_mean.abs() > f64::EPSILON {
+            deviation / self.balance_mean.abs()
+        } else {
+            (deviation > 0.0) as u8 as f64
+        };
+        25.0 * (dispute_frequency.min(1.0) + failure_rate + velocity.min(1.0) + volatility.min(1.0))
+    }
+}
+
+// An account a rule tripped on, for review.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Flag {
+    pub client: u32,
+    pub rule: String,
+    // the transaction that tripped the rule.
+    pub tx: u64,
+    pub timestamp: Option<u64>,
+    pub line: Option<u64>,
+    pub detail: String,
+}
+
+// A row of the flags export.
+#[derive(Debug, Serialize)]
+struct FlagRow<'a> {
+    client: String,
+    rule: &'a str,
+    tx: u64,
+    timestamp: Option<u64>,
+    line: Option<u64>,
+    detail: &'a str,
+}
+
+impl ChargebackRule {
+    // the name of the rule the stats trip, if any, with why.
+    fn tripped(&self, stats: &AccountStats) -> Option<(&'static str, String)> {
+        if let Some(max_chargebacks) = self.max_chargebacks {
+            if stats.chargebacks > max_chargebacks {
+                return Some((
+                    "max-chargebacks",
+                    format!(
+                        "{} charge backs, more than {}",
+                        stats.chargebacks, max_chargebacks
+                    ),
+                ));
+            }
+        }
+        if let Some(max_chargeback_ratio) = self.max_chargeback_ratio {
+            let ratio = stats.charged_back / stats.deposited;
+            if stats.deposited > 0.0 && ratio > max_chargeback_ratio {
+                return Some((
+                    "max-chargeback-ratio",
+                    format!(
+                        "charge backs of {:.2}% of the deposits, more than {:.2}%",
+                        ratio * 100.0,
+                        max_chargeback_ratio * 100.0
+                    ),
+                ));
+            }
+        }
+        None
+    }
+}
+
+impl RiskRule {
+    fn tripped(&self, stats: &AccountStats) -> Option<String> {
+        let score = stats.risk_score();
+        if stats.transactions + stats.failures >= self.min_transactions && score > self.max_score {
+            Some(format!(
+                "risk score of {:.2}, more than {:.2}",
+                score, self.max_score
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl PaymentEngine {
+    // whether a rule may flag the accounts.
+    pub fn has_rules(&self) -> bool {
+        self.chargeback_rule.is_some() || self.risk_rule.is_some()
+    }
+
+    // write the flags as `client,rule,tx,timestamp,line,detail`, in the order they were raised,
+    // for review apart from the failures.
+    #[cfg(feature = "fs")]
+    pub fn export_flags_to_file(&self, flags_output_file_path: String) -> Result<()> {
+        self.write_flags(File::create(flags_output_file_path)?)
+    }
+
+    pub(crate) fn write_flags<W: Write>(&self, writer: W) -> Result<()> {
+        let mut wtr = self.csv_writer_options.writer_builder().from_writer(writer);
+        for flag in self.flags.iter() {
+            wtr.serialize(FlagRow {
+                client: self.client_label(flag.client),
+                rule: &flag.rule,
+                tx: flag.tx,
+                timestamp: flag.timestamp,
+                line: flag.line,
+                detail: &flag.detail,
+            })?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    // whether the stats of the accounts are kept, for a rule or the risk score column.
+    pub(crate) fn tracks_account_stats(&self) -> bool {
+        self.has_rules()
+            || self
+                .account_columns
+                .as_ref()
+                .is_some_and(|columns| columns.contains(&AccountColumn::RiskScore))
+    }
+
+    // count a failure recorded against `record` in the stats of its account.
+    pub(crate) fn count_failure(&mut self, record: &StringRecord) {
+        if !self.tracks_account_stats() {
+            return;
+        }
+        let label = record.get(1).unwrap_or_default().trim();
+        if let Some(client) = self.find_client(label) {
+            self.account_stats.entry(client).or_default().failures += 1;
+        }
+    }
+
+    // run the rules on the account of a transaction just applied; `original_amount` is the amount
+    // of the transaction a charge back or representment refers to.
+    pub(crate) fn apply_rules(
+        &mut self,
+        record: Option<&StringRecord>,
+        transaction: &Transaction,
+        original_amount: Option<f32>,
+    ) {
+        if !self.tracks_account_stats() {
+            return;
+        }
+        let total = self
+            .accounts
+            .get(&transaction.client)
+            .map(|account| account.total)
+            .unwrap_or_default();
+        let stats = self.account_stats.entry(transaction.client).or_default();
+        stats.record(transaction, original_amount, total);
+        let stats = *stats;
+        if let Some(rule) = self.risk_rule {
+            if let Some(detail) = rule.tripped(&stats) {
+                self.flag_account_once(record, transaction, "max-risk-score", detail);
+            }
+        }
+        let rule = match self.chargeback_rule {
+            Some(rule) => rule,
+            None => return,
+        };
+        match transaction.r#type {
+            // the representment unlocked the account.
+            TransactionType::Deposit
+            | TransactionType::ChargeBack
+            | TransactionType::Representment => {}
+            _ => return,
+        }
+        let (name, detail) = match rule.tripped(&stats) {
+            Some(tripped) => tripped,
+            None => return,
+        };
+        if rule.action == RuleAction::Lock {
+            if let Some(account) = self.accounts.get_mut(&transaction.client) {
+                let was_locked = account.locked;
+                account.locked = true;
+                account.locked_by_rule = true;
+                if !was_locked {
+                    for observer in self.observers.iter() {
+                        observer.on_account_locked(account);
+                    }
+                }
+            }
+        }
+        self.flag_account_once(record, transaction, name, detail);
+    }
+
+    // flag the account unless the rule `name` already did.
+    fn flag_account_once(
+        &mut self,
+        record: Option<&StringRecord>,
+        transaction: &Transaction,
+        name: &'static str,
+        detail: String,
+    ) {
+        if self.flagged.insert((transaction.client, name)) {
+            self.flag_account(record, transaction, name, detail);
+        }
+    }
+
+    // flag the account of `transaction` for review by the rule `name`.
+    pub(crate) fn flag_account(
+        &mut self,
+        record: Option<&StringRecord>,
+        transaction: &Transaction,
+        name: &str,
+        detail: String,
+    ) {
+        let flag = Flag {
+            client: transaction.client,
+            rule: name.to_string(),
+            tx: transaction.tx,
+            timestamp: transaction.timestamp,
+            line: record
+                .and_then(|record| record.position())
+                .map(|position| position.line()),
+            detail,
+        };
+        for observer in self.observers.iter() {
+            observer.on_account_flagged(&flag);
+        }
+        self.record_client_warning(
+            Severity::Warning,
+            record,
+            Some(flag.client),
+            format!(
+                "Client {} flagged by rule {}: {}.",
+                self.client_label(flag.client),
+                flag.rule,
+                flag.detail
+            ),
+        );
+        self.flags.push(flag);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_once_per_rule() {
+        let mut engine = PaymentEngine {
+            chargeback_rule: Some(ChargebackRule {
+                max_chargebacks: Some(0),
+                max_chargeback_ratio: None,
+                action: RuleAction::Flag,
+            }),
+            ..Default::default()
+        };
+        engine.parse_transactions_from_str(
+            "type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,1.0
+dispute,1,1
+chargeback,1,1
+representment,1,1
+dispute,1,2
+chargeback,1,2
+deposit,1,3,1.0
+deposit,2,4,1.0
+dispute,2,4
+chargeback,2,4
+",
+        );
+        let flagged: Vec<_> = engine
+            .flags
+            .iter()
+            .map(|flag| (flag.client, flag.rule.as_str(), flag.tx))
+            .collect();
+        assert_eq!(
+            flagged,
+            [(1, "max-chargebacks", 1), (2, "max-chargebacks", 4)]
+        );
+    }
+}
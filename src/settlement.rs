@@ -0,0 +1,88 @@
+use crate::fee::FEE_TYPE;
+use crate::journal::{self, JournalEntry};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+
+// The net movement of a client over the transactions of a journal, as settled with treasury.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Settlement {
+    pub client: String,
+    pub deposits: f64,
+    pub withdrawals: f64,
+    // the amounts of the charged back transactions, less those represented.
+    pub chargebacks: f64,
+    pub fees: f64,
+    // deposits minus withdrawals, chargebacks and fees.
+    pub net: f64,
+}
+
+// the settlement of every client of the journal at `journal_file_path`, sorted by client. Only the
+// applied transactions, their fees and the automatic chargebacks count; the journal is verified first so a tampered one is never settled.
+pub fn settle_journal(journal_file_path: String) -> Result<Vec<Settlement>> {
+    journal::verify_journal(journal_file_path.clone())?;
+    let mut csv_reader = csv::Reader::from_path(journal_file_path)?;
+    // numeric clients sort by number, string clients by name.
+    let mut settlements = BTreeMap::<(Option<u64>, String), Settlement>::new();
+    for entry in csv_reader.deserialize::<JournalEntry>() {
+        let entry = entry?;
+        if entry.event != "applied" && entry.event != "fee" && entry.event != "auto" {
+            continue;
+        }
+        let amount = entry.amount.parse::<f64>().unwrap_or_default();
+        let settlement = settlements
+            .entry((entry.client.parse().ok(), entry.client.clone()))
+            .or_insert_with(|| Settlement {
+                client: entry.client.clone(),
+                ..Default::default()
+            });
+        match entry.r#type.as_str() {
+            "deposit" => settlement.deposits += amount,
+            "withdrawal" | "capture" => settlement.withdrawals += amount,
+            // journaled with the amount charged back.
+            "chargeback" => settlement.chargebacks += amount,
+            "representment" => settlement.chargebacks -= amount,
+            FEE_TYPE => settlement.fees += amount,
+            _ => {}
+        }
+    }
+    Ok(settlements
+        .into_values()
+        .map(|settlement| Settlement {
+            net: settlement.deposits
+                - settlement.withdrawals
+                - settlement.chargebacks
+                - settlement.fees,
+            ..settlement
+        })
+        .collect())
+}
+
+// write the settlement of the journal at `journal_file_path` as
+// `client,deposits,withdrawals,chargebacks,fees,net`, the amounts with four decimals. Returns the number
+// of clients settled.
+pub fn export_settlement(journal_file_path: String, output_file_path: String) -> Result<usize> {
+    let settlements = settle_journal(journal_file_path)?;
+    let mut wtr = csv::Writer::from_writer(File::create(output_file_path)?);
+    wtr.write_record([
+        "client",
+        "deposits",
+        "withdrawals",
+        "chargebacks",
+        "fees",
+        "net",
+    ])?;
+    for settlement in settlements.iter() {
+        wtr.write_record([
+            settlement.client.clone(),
+            format!("{:.4}", settlement.deposits),
+            format!("{:.4}", settlement.withdrawals),
+            format!("{:.4}", settlement.chargebacks),
+            format!("{:.4}", settlement.fees),
+            format!("{:.4}", settlement.net),
+        ])?;
+    }
+    wtr.flush()?;
+    Ok(settlements.len())
+}
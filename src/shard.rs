@@ -0,0 +1,202 @@
+use crate::payment_engine::PaymentEngine;
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Default)]
+pub struct ShardReport {
+    pub shard: usize,
+    // the core the shard's thread was pinned to, if pinning was asked and succeeded.
+    pub core: Option<usize>,
+    pub rows: u64,
+    pub elapsed: Duration,
+}
+
+impl ShardReport {
+    pub fn rows_per_sec(&self) -> f64 {
+        let elapsed = self.elapsed.as_secs_f64();
+        if elapsed > 0.0 {
+            self.rows as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+}
+
+const IDEMPOTENCY_KEY_FIELD: usize = 5;
+
+// the number of shards used when none is given: one per core.
+pub fn default_threads() -> usize {
+    thread::available_parallelism()
+        .map(|threads| threads.get())
+        .unwrap_or(1)
+}
+
+impl PaymentEngine {
+    // same as `parse_transactions` but splits the clients over `threads` shards, each applied by
+    // its own thread on a copy of this engine. Clients never span shards, so their transactions
+    // keep their file order; the results are merged back into this engine, the failures in input
+    // order, so the exports match the sequential ones. The shards only see their own idempotency
+    // keys, so a key used by clients of different shards is refused before any is applied.
+    pub fn parse_transactions_sharded(
+        &mut self,
+        threads: usize,
+        pin_threads: bool,
+    ) -> Result<Vec<ShardReport>> {
+        if threads == 0 {
+            return Err(anyhow!("at least one thread is needed"));
+        }
+        if self.string_clients {
+            // the numeric IDs of string clients are allocated per engine and would collide.
+            return Err(anyhow!("string clients can't be sharded"));
+        }
+        let mut shards = vec![Vec::new(); threads];
+        // (idempotency key, shard of its first record)
+        let mut key_shards = HashMap::new();
+        let mut csv_reader = self.new_file_buff_reader()?;
+        self.check_headers(&mut csv_reader);
+        for record in csv_reader.records() {
+            match record {
+                Ok(record) if self.skips_client(&record) => {}
+                Ok(record) => {
+                    // unparsable clients fail in whichever shard they land.
+                    let client = record
+                        .get(1)
+                        .and_then(|client| client.trim().parse::<u32>().ok())
+                        .unwrap_or_default();
+                    let shard = client as usize % threads;
+                    if let Some(key) = record
+                        .get(IDEMPOTENCY_KEY_FIELD)
+                        .filter(|key| !key.is_empty())
+                    {
+                        let first_shard = *key_shards.entry(key.to_string()).or_insert(shard);
+                        if first_shard != shard {
+                            return Err(anyhow!(
+                                "idempotency key {} is used by clients of different shards, run \
+                                 without threads",
+                                key
+                            ));
+                        }
+                    }
+                    shards[shard].push(record);
+                }
+                Err(e) => eprintln!("Could not read line: {}", e),
+            }
+        }
+        let cores = pin_threads.then(default_threads);
+        let template = &*self;
+        let results = thread::scope(|scope| {
+            let workers = shards
+                .into_iter()
+                .enumerate()
+                .map(|(shard, records)| {
+                    scope.spawn(move || {
+                        // the shard starts from the accounts of its own clients, and records
+                        // its own failures and warnings.
+                        let mut engine = template.clone();
+                        engine
+                            .accounts
+                            .retain(|client, _| *client as usize % threads == shard);
+                        engine
+                            .rounding
+                            .retain(|client, _| *client as usize % threads == shard);
+                        engine
+                            .account_stats
+                            .retain(|client, _| *client as usize % threads == shard);
+                        engine.flags.clear();
+                        engine
+                            .flagged
+                            .retain(|(client, _)| *client as usize % threads == shard);
+                        engine.failed_transactions.clear();
+                        engine.warnings.clear();
+                        engine.skipped_duplicates = 0;
+                        engine.skipped_out_of_range = 0;
+                        engine.fees_charged = 0.0;
+                        let core = cores
+                            .map(|cores| shard % cores)
+                            .filter(|&core| pin_current_thread(core));
+                        let started = Instant::now();
+                        engine.apply_records(&records);
+                        let report = ShardReport {
+                            shard,
+                            core,
+                            rows: records.len() as u64,
+                            elapsed: started.elapsed(),
+                        };
+                        (engine, report)
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("a shard thread panicked"))
+                .collect::<Vec<_>>()
+        });
+        let mut reports = Vec::new();
+        let failed_before = self.failed_transactions.len();
+        let warnings_before = self.warnings.len();
+        let flags_before = self.flags.len();
+        for (engine, report) in results {
+            self.accounts.extend(engine.accounts);
+            self.rounding.extend(engine.rounding);
+            self.account_stats.extend(engine.account_stats);
+            self.flags.extend(engine.flags);
+            self.flagged.extend(engine.flagged);
+            self.failed_transactions.extend(engine.failed_transactions);
+            self.warnings.extend(engine.warnings);
+            self.applied_idempotency_keys
+                .extend(engine.applied_idempotency_keys);
+            self.skipped_duplicates += engine.skipped_duplicates;
+            self.skipped_out_of_range += engine.skipped_out_of_range;
+            self.fees_charged += engine.fees_charged;
+            self.transaction_index.extend(engine.transaction_index)?;
+            reports.push(report);
+        }
+        // stable, keeping the order of the failures of a record.
+        self.failed_transactions[failed_before..].sort_by_key(|failed_tx| failed_tx.line);
+        self.warnings[warnings_before..].sort_by_key(|warning| warning.line);
+        self.flags[flags_before..].sort_by_key(|flag| flag.line);
+        Ok(reports)
+    }
+
+    // whether both engines write byte-identical accounts, failures, warnings and flags exports,
+    // e.g. an engine run sharded and a copy run sequentially.
+    pub fn same_exports(&self, other: &PaymentEngine) -> Result<bool> {
+        let export = |engine: &PaymentEngine| -> Result<[Vec<u8>; 4]> {
+            let mut exports: [Vec<u8>; 4] = Default::default();
+            let [accounts, failed_txs, warnings, flags] = &mut exports;
+            engine.write_accounts(accounts)?;
+            engine.write_failed_txs(failed_txs)?;
+            engine.write_warnings(warnings)?;
+            engine.write_flags(flags)?;
+            Ok(exports)
+        };
+        Ok(export(self)? == export(other)?)
+    }
+
+    fn apply_records(&mut self, records: &[StringRecord]) {
+        for record in records.iter() {
+            match self.deserialize_record(record) {
+                Ok(deserialized_record) => self.apply_transaction(record, &deserialized_record),
+                Err(e) => self.record_failure(record, e),
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(core: usize) -> bool {
+    // SAFETY: `cpu_set_t` is a plain bit set, valid when zeroed, and only read by the call.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == 0
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_core: usize) -> bool {
+    false
+}
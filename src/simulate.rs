@@ -0,0 +1,120 @@
+use crate::account::Account;
+use crate::compact;
+use crate::payment_engine::{PaymentEngine, Transaction, TransactionType};
+
+// What applying a transaction would do, as told by `simulate`.
+#[derive(Debug, Clone)]
+pub struct SimulationResult {
+    // the account as it would be after the transaction, without its history.
+    pub account: Account,
+    // the first failure applying it would record, if any.
+    pub error: Option<String>,
+    // it would be skipped, its idempotency key being already applied.
+    pub duplicate: bool,
+    // it would be skipped, being out of the `from_timestamp`..`to_timestamp` range.
+    pub out_of_range: bool,
+}
+
+impl PaymentEngine {
+    // what applying the transaction would do to its account, following the same steps as
+    // `apply_transaction` on a copy of the account, so the engine is left untouched. This lets a
+    // caller check a transaction, e.g. whether a withdrawal would go through, before submitting it.
+    pub fn simulate(&self, transaction: &Transaction) -> SimulationResult {
+        let class = self
+            .clients
+            .get(&transaction.client)
+            .map(|metadata| metadata.class)
+            .unwrap_or_default();
+        let mut account = match self.accounts.get(&transaction.client) {
+            Some(account) => account.clone(),
+            None => Account {
+                client: transaction.client,
+                class,
+                ..Default::default()
+            },
+        };
+        let mut result = SimulationResult {
+            account: Account::default(),
+            error: None,
+            duplicate: false,
+            out_of_range: false,
+        };
+        if !self.in_date_range(transaction) {
+            result.out_of_range = true;
+        } else if transaction
+            .idempotency_key
+            .as_ref()
+            .is_some_and(|key| self.applied_idempotency_keys.contains_key(key))
+        {
+            result.duplicate = true;
+        } else {
+            let mut errors = Vec::new();
+            if transaction.amount == 0.0
+                && (transaction.r#type == TransactionType::Deposit
+                    || transaction.r#type == TransactionType::Withdrawal)
+            {
+                errors.push(format!(
+                    "{:?} transaction must be above zero",
+                    transaction.r#type
+                ));
+            }
+            let restored = if self.compact
+                && transaction.r#type != TransactionType::Deposit
+                && transaction.r#type != TransactionType::Withdrawal
+                && transaction.r#type != TransactionType::Authorize
+            {
+                compact::restore(&self.transaction_index, &mut account, transaction.tx)
+            } else {
+                Ok(())
+            };
+            match restored {
+                Err(e) => errors.push(e.to_string()),
+                Ok(()) => {
+                    let fee = match &self.fee_schedule {
+                        Some(fee_schedule) if !account.locked => {
+                            fee_schedule.fee(transaction, account.class)
+                        }
+                        _ => None,
+                    };
+                    let uncovered_fee = fee.filter(|fee| !account.covers_fee(transaction, *fee));
+                    if let Some(fee) = uncovered_fee {
+                        errors.push(format!(
+                            "Can't apply the {:?}; insufficient funds for its fee of {:.4}.",
+                            transaction.r#type, fee
+                        ));
+                    } else {
+                        let processed = match &transaction.r#type {
+                            TransactionType::Unknown(r#type) => match self.handlers.get(r#type) {
+                                Some(handler) => account
+                                    .process_custom_transaction(handler.as_ref(), transaction),
+                                None => account.process_transaction(transaction),
+                            },
+                            _ => account.process_transaction(transaction),
+                        };
+                        match (processed, fee) {
+                            (Err(e), _) => errors.push(e.to_string()),
+                            (Ok(()), Some(fee)) => account.charge_fee(fee),
+                            (Ok(()), None) => {}
+                        }
+                        let violation = if !self.check_invariants {
+                            None
+                        } else if self.compact {
+                            account.check_balances().err()
+                        } else {
+                            account.check_invariants().err()
+                        };
+                        if let Some(e) = violation {
+                            errors.push(e.to_string());
+                        }
+                    }
+                }
+            }
+            result.error = errors.into_iter().next();
+        }
+        result.account = Account {
+            transactions: Default::default(),
+            ..account
+        };
+        result
+    }
+}
@@ -0,0 +1,90 @@
+// A slab of values addressed by 32-bit handles: the values share one vector and freed slots are
+// reused, instead of being allocated one by one.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle(u32);
+
+#[derive(Debug, Clone)]
+pub struct Slab<T> {
+    slots: Vec<Option<T>>,
+    // vacant slots, reused before growing.
+    free: Vec<u32>,
+}
+
+impl<T> Default for Slab<T> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+}
+
+impl<T> Slab<T> {
+    // panics past `u32::MAX` values.
+    pub fn insert(&mut self, value: T) -> Handle {
+        match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot as usize] = Some(value);
+                Handle(slot)
+            }
+            None => {
+                let slot = u32::try_from(self.slots.len()).expect("the slab is full");
+                self.slots.push(Some(value));
+                Handle(slot)
+            }
+        }
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.slots.get(handle.0 as usize)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        self.slots.get_mut(handle.0 as usize)?.as_mut()
+    }
+
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        let value = self.slots.get_mut(handle.0 as usize)?.take();
+        if value.is_some() {
+            self.free.push(handle.0);
+        }
+        value
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handle_reuse() {
+        let mut slab = Slab::default();
+        let handles = (0..4).map(|value| slab.insert(value)).collect::<Vec<_>>();
+        assert_eq!(slab.remove(handles[1]), Some(1));
+        assert_eq!(slab.remove(handles[1]), None);
+        assert_eq!(slab.get(handles[1]), None);
+        assert_eq!(slab.len(), 3);
+        // the freed slot is reused before the slab grows.
+        let reused = slab.insert(10);
+        assert_eq!(reused, handles[1]);
+        assert_eq!(slab.get(reused), Some(&10));
+        let grown = slab.insert(11);
+        assert_eq!(grown, Handle(4));
+        *slab.get_mut(handles[3]).unwrap() += 30;
+        let values = [0, 10, 2, 33, 11];
+        for (slot, value) in values.iter().enumerate() {
+            assert_eq!(slab.get(Handle(slot as u32)), Some(value));
+        }
+        assert_eq!(slab.len(), 5);
+        assert_eq!(slab.get(Handle(5)), None);
+    }
+}
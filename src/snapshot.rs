@@ -0,0 +1,431 @@
+use crate::account::{Account, AccountClass};
+use crate::client::ClientMetadata;
+use crate::hold::{Hold, OpenHolds};
+use crate::payment_engine::{PaymentEngine, Transaction, TransactionType};
+use anyhow::{anyhow, Result};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Take, Write};
+
+const MAGIC: &[u8; 8] = b"PESNAP4\n";
+
+// What `client_report` tells about a client.
+#[derive(Debug, Clone)]
+pub struct ClientReport {
+    pub account: Account,
+    // the transactions under dispute, by ID.
+    pub open_disputes: Vec<Transaction>,
+    // the transactions of the history with the highest IDs, the highest first.
+    pub highest: Vec<Transaction>,
+}
+
+impl PaymentEngine {
+    // write the accounts and their transaction histories to a binary snapshot, to answer
+    // queries later without processing the input again, along with what the next run over it
+    // needs: the applied idempotency keys, the client metadata and the holds and disputes that may
+    // expire. The compact index is not written, so compact engines can't be snapshotted.
+    pub fn write_snapshot(&self, snapshot_file_path: String) -> Result<()> {
+        if self.compact {
+            return Err(anyhow!("compact engines keep no history to snapshot"));
+        }
+        let mut writer = BufWriter::new(File::create(snapshot_file_path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[self.string_clients as u8])?;
+        write_u64(&mut writer, self.client_labels.len() as u64)?;
+        for label in self.client_labels.iter() {
+            write_string(&mut writer, label)?;
+        }
+        write_u64(&mut writer, self.accounts.len() as u64)?;
+        for account in self.accounts.values() {
+            writer.write_all(&account.client.to_le_bytes())?;
+            for balance in [account.available, account.held, account.total] {
+                writer.write_all(&balance.to_le_bytes())?;
+            }
+            let locked = account.locked as u8 | (account.locked_by_rule as u8) << 1;
+            writer.write_all(&[locked, class_code(account.class)])?;
+            write_option_u64(&mut writer, account.evicted_up_to)?;
+            write_u64(&mut writer, account.chargebacks)?;
+            write_u64(&mut writer, account.transactions.len() as u64)?;
+            for transaction in account.transactions.values() {
+                write_transaction(&mut writer, transaction)?;
+            }
+        }
+        let mut idempotency_keys = self.applied_idempotency_keys.iter().collect::<Vec<_>>();
+        idempotency_keys.sort();
+        write_u64(&mut writer, idempotency_keys.len() as u64)?;
+        for (idempotency_key, client) in idempotency_keys {
+            write_string(&mut writer, idempotency_key)?;
+            writer.write_all(&client.to_le_bytes())?;
+        }
+        write_u64(&mut writer, self.clients.len() as u64)?;
+        for metadata in self.clients.values() {
+            writer.write_all(&metadata.client.to_le_bytes())?;
+            for field in [&metadata.name, &metadata.tier, &metadata.country] {
+                write_string(&mut writer, field)?;
+            }
+            writer.write_all(&[class_code(metadata.class)])?;
+        }
+        for open_holds in [&self.open_authorizations, &self.open_disputes] {
+            write_open_holds(&mut writer, open_holds)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    // an engine holding the accounts of a snapshot written by `write_snapshot`.
+    pub fn read_snapshot(snapshot_file_path: String) -> Result<Self> {
+        let file = File::open(&snapshot_file_path)?;
+        // the lengths read are bounded by what is left of the file, so a corrupt one can't make
+        // us allocate more than its size.
+        let size = file.metadata()?.len();
+        let mut reader = BufReader::new(file).take(size);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(anyhow!("{} is not a snapshot", snapshot_file_path));
+        }
+        let mut engine = PaymentEngine {
+            string_clients: read_u8(&mut reader)? != 0,
+            ..Default::default()
+        };
+        for client in 0..read_len(&mut reader)? {
+            let label = read_string(&mut reader)?;
+            engine.client_ids.insert(label.clone(), client as u32);
+            engine.client_labels.push(label);
+        }
+        for _ in 0..read_len(&mut reader)? {
+            let mut account = Account {
+                client: read_u32(&mut reader)?,
+                available: read_f32(&mut reader)?,
+                held: read_f32(&mut reader)?,
+                total: read_f32(&mut reader)?,
+                ..Default::default()
+            };
+            let locked = read_u8(&mut reader)?;
+            account.locked = locked & 1 != 0;
+            account.locked_by_rule = locked & 2 != 0;
+            account.class = read_class(&mut reader)?;
+            account.evicted_up_to = read_option_u64(&mut reader)?;
+            account.chargebacks = read_u64(&mut reader)?;
+            for _ in 0..read_len(&mut reader)? {
+                let transaction = read_transaction(&mut reader)?;
+                account.transactions.insert(transaction.tx, transaction);
+            }
+            engine.accounts.insert(account.client, account);
+        }
+        for _ in 0..read_len(&mut reader)? {
+            let idempotency_key = read_string(&mut reader)?;
+            engine
+                .applied_idempotency_keys
+                .insert(idempotency_key, read_u32(&mut reader)?);
+        }
+        for _ in 0..read_len(&mut reader)? {
+            let metadata = ClientMetadata {
+                client: read_u32(&mut reader)?,
+                name: read_string(&mut reader)?,
+                tier: read_string(&mut reader)?,
+                country: read_string(&mut reader)?,
+                class: read_class(&mut reader)?,
+            };
+            engine.clients.insert(metadata.client, metadata);
+        }
+        engine.open_authorizations = read_open_holds(&mut reader)?;
+        engine.open_disputes = read_open_holds(&mut reader)?;
+        Ok(engine)
+    }
+
+    // the balances, open disputes and the `highest` transactions with the highest IDs of a client,
+    // if it has an account.
+    pub fn client_report(&self, client: u32, highest: usize) -> Option<ClientReport> {
+        let account = self.accounts.get(&client)?;
+        Some(ClientReport {
+            open_disputes: account
+                .transactions
+                .values()
+                .filter(|transaction| transaction.disputed)
+                .cloned()
+                .collect(),
+            highest: account
+                .transactions
+                .values()
+                .rev()
+                .take(highest)
+                .cloned()
+                .collect(),
+            account: Account {
+                transactions: Default::default(),
+                ..account.clone()
+            },
+        })
+    }
+}
+
+fn class_code(class: AccountClass) -> u8 {
+    match class {
+        AccountClass::Checking => 0,
+        AccountClass::Savings => 1,
+        AccountClass::Merchant => 2,
+    }
+}
+
+fn read_class<R: Read>(reader: &mut R) -> Result<AccountClass> {
+    match read_u8(reader)? {
+        0 => Ok(AccountClass::Checking),
+        1 => Ok(AccountClass::Savings),
+        2 => Ok(AccountClass::Merchant),
+        class => Err(anyhow!("unknown account class {} in snapshot", class)),
+    }
+}
+
+fn write_open_holds<W: Write>(writer: &mut W, open_holds: &OpenHolds) -> Result<()> {
+    write_u64(writer, open_holds.clock)?;
+    write_option_u64(writer, open_holds.latest_timestamp)?;
+    write_u64(writer, open_holds.holds().count() as u64)?;
+    for (clock, hold) in open_holds.holds() {
+        write_u64(writer, clock)?;
+        writer.write_all(&hold.client.to_le_bytes())?;
+        write_u64(writer, hold.tx)?;
+        write_option_u64(writer, hold.timestamp)?;
+    }
+    Ok(())
+}
+
+fn read_open_holds<R: Read>(reader: &mut Take<R>) -> Result<OpenHolds> {
+    let mut open_holds = OpenHolds::default();
+    open_holds.clock = read_u64(reader)?;
+    open_holds.latest_timestamp = read_option_u64(reader)?;
+    for _ in 0..read_len(reader)? {
+        let clock = read_u64(reader)?;
+        let hold = Hold {
+            client: read_u32(reader)?,
+            tx: read_u64(reader)?,
+            timestamp: read_option_u64(reader)?,
+        };
+        open_holds.restore(clock, hold);
+    }
+    Ok(open_holds)
+}
+
+fn write_transaction<W: Write>(writer: &mut W, transaction: &Transaction) -> Result<()> {
+    write_u64(writer, transaction.tx)?;
+    writer.write_all(&transaction.client.to_le_bytes())?;
+    writer.write_all(&transaction.amount.to_le_bytes())?;
+    let r#type = match &transaction.r#type {
+        TransactionType::Deposit => 0,
+        TransactionType::Withdrawal => 1,
+        TransactionType::Dispute => 2,
+        TransactionType::Resolve => 3,
+        TransactionType::ChargeBack => 4,
+        TransactionType::Unknown(_) => 5,
+        TransactionType::Authorize => 6,
+        TransactionType::Capture => 7,
+        TransactionType::Representment => 8,
+    };
+    writer.write_all(&[
+        r#type,
+        transaction.disputed as u8,
+        transaction.charged_back as u8,
+        transaction.captured as u8,
+    ])?;
+    for amount in [transaction.captured_amount, transaction.fee] {
+        writer.write_all(&amount.to_le_bytes())?;
+    }
+    if let TransactionType::Unknown(r#type) = &transaction.r#type {
+        write_string(writer, r#type)?;
+    }
+    write_option_u64(writer, transaction.timestamp)?;
+    match &transaction.idempotency_key {
+        Some(idempotency_key) => {
+            writer.write_all(&[1])?;
+            write_string(writer, idempotency_key)
+        }
+        None => Ok(writer.write_all(&[0])?),
+    }
+}
+
+fn read_transaction<R: Read>(reader: &mut Take<R>) -> Result<Transaction> {
+    let tx = read_u64(reader)?;
+    let client = read_u32(reader)?;
+    let amount = read_f32(reader)?;
+    let r#type = match read_u8(reader)? {
+        0 => TransactionType::Deposit,
+        1 => TransactionType::Withdrawal,
+        2 => TransactionType::Dispute,
+        3 => TransactionType::Resolve,
+        4 => TransactionType::ChargeBack,
+        5 => TransactionType::Unknown(String::new()),
+        6 => TransactionType::Authorize,
+        7 => TransactionType::Capture,
+        8 => TransactionType::Representment,
+        r#type => return Err(anyhow!("unknown transaction type {} in snapshot", r#type)),
+    };
+    let disputed = read_u8(reader)? != 0;
+    let charged_back = read_u8(reader)? != 0;
+    let captured = read_u8(reader)? != 0;
+    let captured_amount = read_f32(reader)?;
+    let fee = read_f32(reader)?;
+    let r#type = match r#type {
+        TransactionType::Unknown(_) => TransactionType::Unknown(read_string(reader)?),
+        r#type => r#type,
+    };
+    let timestamp = read_option_u64(reader)?;
+    let idempotency_key = match read_u8(reader)? {
+        0 => None,
+        _ => Some(read_string(reader)?),
+    };
+    Ok(Transaction {
+        r#type,
+        client,
+        tx,
+        amount,
+        timestamp,
+        idempotency_key,
+        disputed,
+        charged_back,
+        captured,
+        captured_amount,
+        fee,
+    })
+}
+
+fn write_u64<W: Write>(writer: &mut W, value: u64) -> Result<()> {
+    Ok(writer.write_all(&value.to_le_bytes())?)
+}
+
+fn write_option_u64<W: Write>(writer: &mut W, value: Option<u64>) -> Result<()> {
+    writer.write_all(&[value.is_some() as u8])?;
+    write_u64(writer, value.unwrap_or_default())
+}
+
+fn write_string<W: Write>(writer: &mut W, value: &str) -> Result<()> {
+    write_u64(writer, value.len() as u64)?;
+    Ok(writer.write_all(value.as_bytes())?)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f32<R: Read>(reader: &mut R) -> Result<f32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn read_option_u64<R: Read>(reader: &mut R) -> Result<Option<u64>> {
+    let is_some = read_u8(reader)? != 0;
+    let value = read_u64(reader)?;
+    Ok(is_some.then_some(value))
+}
+
+// a length or count, which can't be more than the bytes left to read.
+fn read_len<R: Read>(reader: &mut Take<R>) -> Result<u64> {
+    let len = read_u64(reader)?;
+    if len > reader.limit() {
+        return Err(anyhow!(
+            "length {} in snapshot is past its end, {} bytes on",
+            len,
+            reader.limit()
+        ));
+    }
+    Ok(len)
+}
+
+fn read_string<R: Read>(reader: &mut Take<R>) -> Result<String> {
+    let mut bytes = vec![0; read_len(reader)? as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hold::HoldExpiry;
+    use std::fs;
+
+    #[test]
+    fn round_trip() {
+        let mut engine = PaymentEngine {
+            hold_expiry: Some(HoldExpiry {
+                transactions: Some(100),
+                days: None,
+            }),
+            ..Default::default()
+        };
+        engine.clients.insert(
+            2,
+            ClientMetadata {
+                client: 2,
+                name: "Ada".to_string(),
+                tier: "gold".to_string(),
+                country: "GB".to_string(),
+                class: AccountClass::Merchant,
+            },
+        );
+        engine.parse_transactions_from_str(
+            "type,client,tx,amount,timestamp,idempotency_key
+deposit,1,1,10.0,1000,k1
+authorize,1,2,4.0,1001,k2
+deposit,2,3,5.0,,k3
+withdrawal,2,4,1.0
+dispute,1,1,0,1002
+",
+        );
+        let path = std::env::temp_dir().join(format!("pe-snapshot-{}.bin", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        engine.write_snapshot(path.clone()).unwrap();
+        let read = PaymentEngine::read_snapshot(path.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(read.same_exports(&engine).unwrap());
+        assert_eq!(
+            read.applied_idempotency_keys,
+            engine.applied_idempotency_keys
+        );
+        assert_eq!(read.clients, engine.clients);
+        assert_eq!(read.open_authorizations, engine.open_authorizations);
+        assert_eq!(read.open_disputes, engine.open_disputes);
+        assert_eq!(read.open_authorizations.holds().count(), 1);
+        for (client, account) in engine.accounts.iter() {
+            let read_account = &read.accounts[client];
+            assert_eq!(read_account.class, account.class);
+            assert_eq!(
+                read_account.transactions.keys().collect::<Vec<_>>(),
+                account.transactions.keys().collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn lengths_past_the_end() {
+        let path = std::env::temp_dir().join(format!("pe-corrupt-{}.bin", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        // a client label, then an account with no transactions but a count of them.
+        let mut label = MAGIC.to_vec();
+        label.push(1);
+        label.extend(1u64.to_le_bytes());
+        label.extend(u64::MAX.to_le_bytes());
+        let mut count = MAGIC.to_vec();
+        count.push(0);
+        count.extend(0u64.to_le_bytes());
+        count.extend(u64::MAX.to_le_bytes());
+        for bytes in [label, count] {
+            fs::write(&path, bytes).unwrap();
+            let error = PaymentEngine::read_snapshot(path.clone()).unwrap_err();
+            assert!(error.to_string().contains("past its end"), "{}", error);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::Result;
+use csv::ByteRecord;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+
+// position of the timestamp in a transaction record.
+const TIMESTAMP_FIELD: usize = 4;
+
+// A record of a sorted run, behind its sort key: timestamp then position in the input.
+type RunRecord = (u64, u64, ByteRecord);
+
+// Sort the transactions file `input_file_path` by timestamp into `output_file_path`, with an
+// external merge sort holding at most `run_size` records in memory: the runs are sorted one by
+// one into scratch files next to the output, which are then merged. Records with the same
+// timestamp keep their input order, and records without a valid timestamp come first. Returns the
+// number of records sorted.
+pub fn sort_by_timestamp(
+    input_file_path: &str,
+    output_file_path: &str,
+    run_size: usize,
+) -> Result<u64> {
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .from_reader(BufReader::new(File::open(input_file_path)?));
+    let headers = csv_reader.byte_headers()?.clone();
+    let mut run_paths = Vec::new();
+    let mut run = Vec::new();
+    let mut records = 0;
+    let mut record = ByteRecord::new();
+    while csv_reader.read_byte_record(&mut record)? {
+        run.push((timestamp(&record), records, record.clone()));
+        records += 1;
+        if run.len() >= run_size.max(1) {
+            run_paths.push(write_run(output_file_path, run_paths.len(), &mut run)?);
+        }
+    }
+    if !run.is_empty() {
+        run_paths.push(write_run(output_file_path, run_paths.len(), &mut run)?);
+    }
+    let merged = merge_runs(&headers, &run_paths, output_file_path);
+    for run_path in run_paths.iter() {
+        fs::remove_file(run_path)?;
+    }
+    merged?;
+    Ok(records)
+}
+
+fn timestamp(record: &ByteRecord) -> u64 {
+    record
+        .get(TIMESTAMP_FIELD)
+        .and_then(|timestamp| std::str::from_utf8(timestamp).ok())
+        .and_then(|timestamp| timestamp.trim().parse().ok())
+        .unwrap_or_default()
+}
+
+// sort the run and write it, keys first, to the scratch file of run `index`, emptying it.
+fn write_run(output_file_path: &str, index: usize, run: &mut Vec<RunRecord>) -> Result<String> {
+    run.sort_unstable_by_key(|(timestamp, position, _)| (*timestamp, *position));
+    let run_path = format!("{}.run{}", output_file_path, index);
+    let mut wtr = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(BufWriter::new(File::create(&run_path)?));
+    for (timestamp, position, record) in run.drain(..) {
+        let mut row = ByteRecord::new();
+        row.push_field(timestamp.to_string().as_bytes());
+        row.push_field(position.to_string().as_bytes());
+        row.extend(record.iter());
+        wtr.write_byte_record(&row)?;
+    }
+    wtr.flush()?;
+    Ok(run_path)
+}
+
+// the next record of a run, parsed back from its scratch file.
+fn next_run_record(
+    csv_reader: &mut csv::Reader<BufReader<File>>,
+    row: &mut ByteRecord,
+) -> Result<Option<RunRecord>> {
+    if !csv_reader.read_byte_record(row)? {
+        return Ok(None);
+    }
+    let key = |field: usize| -> u64 {
+        let key = row.get(field).unwrap_or_default();
+        std::str::from_utf8(key)
+            .ok()
+            .and_then(|key| key.parse().ok())
+            .unwrap_or_default()
+    };
+    let record = row.iter().skip(2).collect::<ByteRecord>();
+    Ok(Some((key(0), key(1), record)))
+}
+
+fn merge_runs(headers: &ByteRecord, run_paths: &[String], output_file_path: &str) -> Result<()> {
+    let mut wtr = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(BufWriter::new(File::create(output_file_path)?));
+    wtr.write_byte_record(headers)?;
+    let mut readers = Vec::new();
+    for run_path in run_paths.iter() {
+        readers.push(
+            csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(BufReader::new(File::open(run_path)?)),
+        );
+    }
+    let mut row = ByteRecord::new();
+    // (timestamp, position, run) of the first record left in every run.
+    let mut heads = BinaryHeap::new();
+    let mut records = Vec::new();
+    for (run, csv_reader) in readers.iter_mut().enumerate() {
+        let head = next_run_record(csv_reader, &mut row)?;
+        if let Some((timestamp, position, _)) = &head {
+            heads.push(Reverse((*timestamp, *position, run)));
+        }
+        records.push(head);
+    }
+    while let Some(Reverse((_, _, run))) = heads.pop() {
+        if let Some((_, _, record)) = records[run].take() {
+            wtr.write_byte_record(&record)?;
+        }
+        let head = next_run_record(&mut readers[run], &mut row)?;
+        if let Some((timestamp, position, _)) = &head {
+            heads.push(Reverse((*timestamp, *position, run)));
+        }
+        records[run] = head;
+    }
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,291 @@
+use crate::account::Account;
+use crate::payment_engine::PaymentEngine;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Balance {
+    pub available: f32,
+    pub held: f32,
+    pub total: f32,
+}
+
+impl From<&Account> for Balance {
+    fn from(account: &Account) -> Self {
+        Self {
+            available: account.available,
+            held: account.held,
+            total: account.total,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StatementItem {
+    pub tx: u64,
+    // the type as written in the input.
+    pub r#type: String,
+    pub timestamp: Option<u64>,
+    pub amount: f32,
+    // the balance right after the transaction.
+    pub balance: Balance,
+    // why the transaction was rejected, if it was.
+    pub failure: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub client: u32,
+    pub opening: Balance,
+    pub items: Vec<StatementItem>,
+    pub closing: Balance,
+}
+
+#[derive(Debug, Serialize)]
+struct StatementRow<'a> {
+    client: u32,
+    line: &'a str,
+    tx: Option<u64>,
+    r#type: Option<&'a str>,
+    timestamp: Option<u64>,
+    amount: Option<String>,
+    available: String,
+    held: String,
+    total: String,
+    failure: Option<&'a str>,
+}
+
+impl PaymentEngine {
+    // process the transactions file and build a statement per client for the period between the
+    // `from` and `to` unix timestamps, both inclusive. Transactions after the period are not
+    // applied. Transactions without a timestamp, like those before the period, are folded into the
+    // opening balance wherever they appear, so that the items always lead from the opening balance
+    // to the closing one.
+    pub fn statements(&mut self, from: u64, to: u64) -> Result<Vec<Statement>> {
+        let mut csv_reader = self.new_file_buff_reader()?;
+        let mut statements: BTreeMap<u32, Statement> = BTreeMap::new();
+        for record in csv_reader.records() {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    eprintln!("Could not read line: {}", e);
+                    continue;
+                }
+            };
+            let transaction = match self.deserialize_record(&record) {
+                Ok(transaction) => transaction,
+                Err(e) => {
+                    self.record_failure(&record, e);
+                    continue;
+                }
+            };
+            match transaction.timestamp {
+                Some(timestamp) if timestamp > to => continue,
+                Some(timestamp) if timestamp >= from => {}
+                _ => {
+                    let before = self.balance(transaction.client);
+                    self.apply_transaction(&record, &transaction);
+                    // after the first item, the change shifts every balance of the statement.
+                    if let Some(statement) = statements.get_mut(&transaction.client) {
+                        let after = self.balance(transaction.client);
+                        let shift = |balance: &mut Balance| {
+                            balance.available += after.available - before.available;
+                            balance.held += after.held - before.held;
+                            balance.total += after.total - before.total;
+                        };
+                        shift(&mut statement.opening);
+                        statement
+                            .items
+                            .iter_mut()
+                            .for_each(|item| shift(&mut item.balance));
+                        shift(&mut statement.closing);
+                    }
+                    continue;
+                }
+            }
+
+            let opening = self.balance(transaction.client);
+            let statement = statements
+                .entry(transaction.client)
+                .or_insert_with(|| Statement {
+                    client: transaction.client,
+                    opening,
+                    items: Vec::new(),
+                    closing: opening,
+                });
+            let failed_before = self.failed_transactions.len();
+            self.apply_transaction(&record, &transaction);
+            let failure = self.failed_transactions[failed_before..]
+                .last()
+                .map(ToString::to_string);
+            let balance = self.balance(transaction.client);
+            statement.items.push(StatementItem {
+                tx: transaction.tx,
+                r#type: record.get(0).unwrap_or_default().to_string(),
+                timestamp: transaction.timestamp,
+                amount: transaction.amount,
+                balance,
+                failure,
+            });
+            statement.closing = balance;
+        }
+
+        // clients without activity in the period still get a statement of their balance.
+        for (client, account) in self.accounts.iter() {
+            statements.entry(*client).or_insert_with(|| Statement {
+                client: *client,
+                opening: Balance::from(account),
+                items: Vec::new(),
+                closing: Balance::from(account),
+            });
+        }
+        Ok(statements.into_values().collect())
+    }
+
+    fn balance(&self, client: u32) -> Balance {
+        self.accounts
+            .get(&client)
+            .map(Balance::from)
+            .unwrap_or_default()
+    }
+
+    pub fn export_statements_to_file(
+        statements: &[Statement],
+        output_file_path: String,
+    ) -> Result<()> {
+        let mut wtr = csv::Writer::from_path(output_file_path)?;
+        for statement in statements.iter() {
+            wtr.serialize(StatementRow::balance(
+                statement.client,
+                "opening",
+                &statement.opening,
+            ))?;
+            for item in statement.items.iter() {
+                wtr.serialize(StatementRow {
+                    tx: Some(item.tx),
+                    r#type: Some(&item.r#type),
+                    timestamp: item.timestamp,
+                    amount: Some(format!("{:.4}", item.amount)),
+                    failure: item.failure.as_deref(),
+                    ..StatementRow::balance(statement.client, "transaction", &item.balance)
+                })?;
+            }
+            wtr.serialize(StatementRow::balance(
+                statement.client,
+                "closing",
+                &statement.closing,
+            ))?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    // render the statements as plain text, one block per client separated by form feeds so they
+    // can be printed one per page.
+    pub fn render_statements_text(&self, statements: &[Statement], from: u64, to: u64) -> String {
+        let mut pages = Vec::new();
+        for statement in statements.iter() {
+            let mut page = format!("Statement for client {}", statement.client);
+            if let Some(metadata) = self.clients.get(&statement.client) {
+                page.push_str(&format!(
+                    "\n{} ({}, {})",
+                    metadata.name, metadata.tier, metadata.country
+                ));
+            }
+            page.push_str(&format!("\nPeriod: {} - {}\n\n", from, to));
+            page.push_str(&format!(
+                "{:<12} {:>10} {:<12} {:>14} {:>14}\n",
+                "timestamp", "tx", "type", "amount", "total"
+            ));
+            page.push_str(&format!(
+                "{:<12} {:>10} {:<12} {:>14} {:>14.4}\n",
+                "", "", "opening", "", statement.opening.total
+            ));
+            for item in statement.items.iter() {
+                page.push_str(&format!(
+                    "{:<12} {:>10} {:<12} {:>14.4} {:>14.4}\n",
+                    item.timestamp.map(|t| t.to_string()).unwrap_or_default(),
+                    item.tx,
+                    item.r#type,
+                    item.amount,
+                    item.balance.total
+                ));
+                if let Some(failure) = &item.failure {
+                    page.push_str(&format!("{:<12} rejected: {}\n", "", failure));
+                }
+            }
+            page.push_str(&format!(
+                "{:<12} {:>10} {:<12} {:>14} {:>14.4}\n\n",
+                "", "", "closing", "", statement.closing.total
+            ));
+            page.push_str(&format!(
+                "Available: {:.4}  Held: {:.4}  Total: {:.4}\n",
+                statement.closing.available, statement.closing.held, statement.closing.total
+            ));
+            pages.push(page);
+        }
+        pages.join("\u{c}")
+    }
+}
+
+impl<'a> StatementRow<'a> {
+    fn balance(client: u32, line: &'a str, balance: &Balance) -> Self {
+        Self {
+            client,
+            line,
+            tx: None,
+            r#type: None,
+            timestamp: None,
+            amount: None,
+            available: format!("{:.4}", balance.available),
+            held: format!("{:.4}", balance.held),
+            total: format!("{:.4}", balance.total),
+            failure: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn items_lead_to_the_closing_balance() {
+        let path = std::env::temp_dir().join(format!("statement-{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "type,client,tx,amount,timestamp
+deposit,1,1,5.0,
+deposit,1,2,1.0,100
+deposit,1,3,2.0,
+withdrawal,1,4,1.5,50
+deposit,1,5,4.0,300
+deposit,2,6,3.0,
+",
+        )
+        .unwrap();
+        let mut engine = PaymentEngine::new(path.to_string_lossy().into_owned());
+        let statements = engine.statements(100, 200).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let first = &statements[0];
+        // the undated and earlier transactions after the first item are in the opening balance.
+        assert_eq!(first.opening.total, 5.5);
+        let items: Vec<_> = first
+            .items
+            .iter()
+            .map(|item| (item.tx, item.timestamp, item.balance.total))
+            .collect();
+        assert_eq!(items, [(2, Some(100), 6.5)]);
+        let listed: f32 = first.items.iter().map(|item| item.amount).sum();
+        assert_eq!(first.opening.total + listed, first.closing.total);
+        assert_eq!(first.closing, first.items.last().unwrap().balance);
+        assert_eq!(first.closing.total, engine.accounts[&1].total);
+
+        // without a transaction in the period, the client's balance is its opening one.
+        let second = &statements[1];
+        assert!(second.items.is_empty());
+        assert_eq!(second.closing.total, 3.0);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::failure::FailedTransaction;
+use crate::payment_engine::PaymentEngine;
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use std::collections::BTreeMap;
+
+// name of the column holding the tenant of a transaction.
+const TENANT_COLUMN: &str = "tenant";
+
+// Engines of several tenants processed together, each with its own accounts and failures.
+#[derive(Debug, Clone, Default)]
+pub struct TenantEngines {
+    // settings every tenant engine starts from.
+    template: PaymentEngine,
+    // (tenant, engine)
+    pub tenants: BTreeMap<String, PaymentEngine>,
+    // records whose tenant is missing or invalid, they belong to no tenant.
+    pub failed_without_tenant: Vec<FailedTransaction>,
+}
+
+impl TenantEngines {
+    pub fn new(template: PaymentEngine) -> Self {
+        Self {
+            template,
+            ..Default::default()
+        }
+    }
+
+    // the engine of a tenant, created from the template on first use.
+    pub fn engine(&mut self, tenant: &str) -> &mut PaymentEngine {
+        let template = &self.template;
+        self.tenants
+            .entry(tenant.to_string())
+            .or_insert_with(|| template.clone())
+    }
+
+    // parse the template's transactions file, dispatching each record to the engine of the
+    // tenant in its `tenant` column, or to `default_tenant` if the file has no such column.
+    pub fn parse_transactions(&mut self, default_tenant: Option<&str>) -> Result<()> {
+        let mut csv_reader = self.template.new_file_buff_reader()?;
+        let tenant_column = csv_reader
+            .headers()?
+            .iter()
+            .position(|header| header == TENANT_COLUMN);
+        if tenant_column.is_none() && default_tenant.is_none() {
+            return Err(anyhow!(
+                "the transactions have no {} column and no default tenant was given",
+                TENANT_COLUMN
+            ));
+        }
+
+        for record in csv_reader.records() {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    eprintln!("Could not read line: {}", e);
+                    continue;
+                }
+            };
+            let (tenant, record) = match tenant_column {
+                Some(column) => (
+                    record.get(column).unwrap_or_default().to_string(),
+                    record
+                        .iter()
+                        .enumerate()
+                        .filter(|(position, _)| *position != column)
+                        .map(|(_, field)| field)
+                        .collect::<StringRecord>(),
+                ),
+                None => (default_tenant.unwrap_or_default().to_string(), record),
+            };
+            if let Err(e) = validate_tenant(&tenant) {
+                self.failed_without_tenant
+                    .push(FailedTransaction::new(&record, &e));
+                continue;
+            }
+            let engine = self.engine(&tenant);
+            if engine.skips_client(&record) {
+                continue;
+            }
+            match engine.deserialize_record(&record) {
+                Ok(transaction) => engine.apply_transaction(&record, &transaction),
+                Err(e) => engine.record_failure(&record, e),
+            }
+        }
+        Ok(())
+    }
+}
+
+// tenants name their output directories, so only plain names are accepted.
+pub fn validate_tenant(tenant: &str) -> Result<()> {
+    let valid = !tenant.is_empty()
+        && tenant
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Can't process transaction; invalid tenant {:?}.",
+            tenant
+        ))
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::{anyhow, Result};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+// parse the start of a date range: either unix seconds or a `YYYY-MM-DD` date, taken at midnight
+// UTC.
+pub fn parse_range_start(s: &str) -> Result<u64> {
+    parse(s, 0)
+}
+
+// parse the inclusive end of a date range: either unix seconds or a `YYYY-MM-DD` date, taken at
+// the last second of that day.
+pub fn parse_range_end(s: &str) -> Result<u64> {
+    parse(s, SECONDS_PER_DAY - 1)
+}
+
+// parse a `YYYYMMDD` date, taken at midnight UTC, ignoring whatever follows it.
+pub fn parse_basic_date(s: &str) -> Result<u64> {
+    let date = s
+        .as_bytes()
+        .get(..8)
+        .filter(|date| date.iter().all(u8::is_ascii_digit))
+        .ok_or_else(|| anyhow!("{} is not a YYYYMMDD date", s))?;
+    // ASCII digits, so every byte is a char boundary.
+    let date = std::str::from_utf8(date)?;
+    parse_range_start(&format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..]))
+}
+
+fn parse(s: &str, seconds_into_day: u64) -> Result<u64> {
+    let s = s.trim();
+    if let Ok(timestamp) = s.parse::<u64>() {
+        return Ok(timestamp);
+    }
+    let parts = s.split('-').collect::<Vec<&str>>();
+    let (year, month, day) = match parts.as_slice() {
+        [year, month, day] => (
+            year.parse::<i64>()?,
+            month.parse::<u32>()?,
+            day.parse::<u32>()?,
+        ),
+        _ => {
+            return Err(anyhow!(
+                "{} is neither unix seconds nor a YYYY-MM-DD date",
+                s
+            ))
+        }
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(anyhow!("{} is not a valid date", s));
+    }
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return Err(anyhow!("{} is before 1970-01-01", s));
+    }
+    Ok(days as u64 * SECONDS_PER_DAY + seconds_into_day)
+}
+
+// days since 1970-01-01 of a proleptic gregorian date, see
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
@@ -0,0 +1,106 @@
+use crate::failure::{self, FailureMask};
+use crate::payment_engine::PaymentEngine;
+use anyhow::Result;
+use csv::StringRecord;
+use serde::Serialize;
+#[cfg(feature = "fs")]
+use std::fs::File;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    // the input was accepted as is, after a normalization.
+    Info,
+    // part of the input was skipped or ignored.
+    Warning,
+}
+
+// A condition worth reporting that is not a failure: the record, if any, was still processed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Warning {
+    pub severity: Severity,
+    // the record the warning is about, empty for a warning about the whole file.
+    pub fields: Vec<String>,
+    pub line: Option<u64>,
+    pub message: String,
+    // the client the warning is about, if any.
+    #[serde(skip)]
+    pub client: Option<u32>,
+}
+
+// A row of the warnings export.
+#[derive(Debug, Serialize)]
+struct WarningRow<'a> {
+    severity: Severity,
+    line: Option<u64>,
+    record: String,
+    message: &'a str,
+}
+
+impl PaymentEngine {
+    pub(crate) fn record_warning(
+        &mut self,
+        severity: Severity,
+        record: Option<&StringRecord>,
+        message: String,
+    ) {
+        let client = record
+            .and_then(|record| record.get(failure::CLIENT_FIELD))
+            .and_then(|client| self.find_client(client.trim()));
+        self.record_client_warning(severity, record, client, message);
+    }
+
+    // record a warning about `client`, which may have no record, e.g. a charge back of a stale
+    // dispute.
+    pub(crate) fn record_client_warning(
+        &mut self,
+        severity: Severity,
+        record: Option<&StringRecord>,
+        client: Option<u32>,
+        message: String,
+    ) {
+        self.warnings.push(Warning {
+            severity,
+            fields: record
+                .map(|record| record.iter().map(str::to_string).collect())
+                .unwrap_or_default(),
+            line: record
+                .and_then(|record| record.position())
+                .map(|position| position.line()),
+            message,
+            client,
+        });
+    }
+
+    // write the warnings as `severity,line,record,message`, the records masked like the failed
+    // transactions.
+    #[cfg(feature = "fs")]
+    pub fn export_warnings_to_file(&self, warnings_output_file_path: String) -> Result<()> {
+        self.write_warnings(File::create(warnings_output_file_path)?)
+    }
+
+    pub(crate) fn write_warnings<W: Write>(&self, writer: W) -> Result<()> {
+        let mut wtr = self.csv_writer_options.writer_builder().from_writer(writer);
+        for warning in self.warnings.iter() {
+            wtr.serialize(WarningRow {
+                severity: warning.severity,
+                line: warning.line,
+                record: failure::mask_fields(&warning.fields, &self.failure_mask).join(","),
+                message: &warning.message,
+            })?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+impl Warning {
+    pub fn masked(&self, mask: &FailureMask) -> String {
+        format!(
+            "{},{}",
+            failure::mask_fields(&self.fields, mask).join(","),
+            self.message
+        )
+    }
+}
@@ -0,0 +1,115 @@
+//! In-memory API for WebAssembly hosts, built with the `wasm` feature.
+//!
+//! The engine is created, fed and released through the C API of `src/ffi.rs`; the functions here
+//! add what a host without a file system needs on top of it: buffers in the module's memory to
+//! pass CSV text in and get JSON out. A JavaScript host allocates `len` bytes with `pe_alloc`,
+//! copies the UTF-8 text into them, calls `pe_apply_csv` and releases the buffer with `pe_free`;
+//! `pe_accounts_json` returns a buffer of its own, released the same way.
+
+use crate::ffi::{PE_INVALID_ARGUMENT, PE_OK};
+use crate::payment_engine::PaymentEngine;
+use std::os::raw::c_int;
+
+/// Allocate a buffer of `len` bytes, to be released with `pe_free`.
+#[no_mangle]
+pub extern "C" fn pe_alloc(len: usize) -> *mut u8 {
+    Box::into_raw(vec![0; len].into_boxed_slice()) as *mut u8
+}
+
+/// Release a buffer returned by `pe_alloc` or `pe_accounts_json`.
+///
+/// # Safety
+/// `buffer` must be null or a buffer of `len` bytes returned by this module and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn pe_free(buffer: *mut u8, len: usize) {
+    if !buffer.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            buffer, len,
+        )));
+    }
+}
+
+/// Process the `len` bytes of CSV text at `csv`, with the header and columns of the input file.
+/// The rejected records are kept as failed transactions, like those of a file.
+///
+/// # Safety
+/// `engine` must be a live pointer returned by `pe_engine_new` and `csv` must point to `len`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn pe_apply_csv(
+    engine: *mut PaymentEngine,
+    csv: *const u8,
+    len: usize,
+) -> c_int {
+    if engine.is_null() || csv.is_null() {
+        return PE_INVALID_ARGUMENT;
+    }
+    match std::str::from_utf8(std::slice::from_raw_parts(csv, len)) {
+        Ok(transactions) => {
+            (*engine).parse_transactions_from_str(transactions);
+            PE_OK
+        }
+        Err(_) => PE_INVALID_ARGUMENT,
+    }
+}
+
+/// The accounts as a JSON array, see `PaymentEngine::export_accounts_to_json`, in a buffer whose
+/// length is written to `len`. Returns null for a null argument.
+///
+/// # Safety
+/// `engine` must be a live pointer returned by `pe_engine_new` and `len` must point to a writable
+/// `usize`.
+#[no_mangle]
+pub unsafe extern "C" fn pe_accounts_json(
+    engine: *const PaymentEngine,
+    len: *mut usize,
+) -> *mut u8 {
+    if engine.is_null() || len.is_null() {
+        return std::ptr::null_mut();
+    }
+    let json = (*engine)
+        .export_accounts_to_json()
+        .into_bytes()
+        .into_boxed_slice();
+    *len = json.len();
+    Box::into_raw(json) as *mut u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::{pe_engine_free, pe_engine_new};
+
+    #[test]
+    fn csv_in_json_out() {
+        let csv =
+            "type,client,tx,amount\ndeposit,1,1,10.0\nwithdrawal,1,2,3.0\nwithdrawal,1,3,30.0\n";
+        let engine = pe_engine_new();
+        unsafe {
+            let buffer = pe_alloc(csv.len());
+            std::ptr::copy_nonoverlapping(csv.as_ptr(), buffer, csv.len());
+            assert_eq!(pe_apply_csv(engine, buffer, csv.len()), PE_OK);
+            pe_free(buffer, csv.len());
+            assert_eq!(
+                pe_apply_csv(engine, [0xff].as_ptr(), 1),
+                PE_INVALID_ARGUMENT
+            );
+
+            let mut len = 0;
+            let json = pe_accounts_json(engine, &mut len);
+            assert_eq!(
+                std::str::from_utf8(std::slice::from_raw_parts(json, len)).unwrap(),
+                r#"[{"client":1,"available":7.0000,"held":0.0000,"total":7.0000,"locked":false}]"#
+            );
+            pe_free(json, len);
+
+            let exports = (*engine).exports_to_strings().unwrap();
+            assert_eq!(
+                exports.accounts,
+                "client,available,held,total,locked\n1,7.0,0.0,7.0,false\n"
+            );
+            assert_eq!(exports.failed_transactions.lines().count(), 1);
+            pe_engine_free(engine);
+        }
+    }
+}